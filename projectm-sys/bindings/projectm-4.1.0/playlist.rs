@@ -0,0 +1,436 @@
+/* automatically generated by rust-bindgen 0.66.1 */
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct projectm {
+    _unused: [u8; 0],
+}
+pub type projectm_handle = *mut projectm;
+pub const projectm_channels_PROJECTM_MONO: projectm_channels = 1;
+pub const projectm_channels_PROJECTM_STEREO: projectm_channels = 2;
+pub type projectm_channels = ::std::os::raw::c_uint;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_RANDOM: projectm_touch_type = 0;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_CIRCLE: projectm_touch_type = 1;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_RADIAL_BLOB: projectm_touch_type = 2;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB2: projectm_touch_type = 3;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB3: projectm_touch_type = 4;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_DERIVATIVE_LINE: projectm_touch_type = 5;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB5: projectm_touch_type = 6;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_LINE: projectm_touch_type = 7;
+pub const projectm_touch_type_PROJECTM_TOUCH_TYPE_DOUBLE_LINE: projectm_touch_type = 8;
+pub type projectm_touch_type = ::std::os::raw::c_uint;
+extern "C" {
+    pub fn projectm_pcm_get_max_samples() -> ::std::os::raw::c_uint;
+}
+extern "C" {
+    pub fn projectm_pcm_add_float(
+        instance: projectm_handle,
+        samples: *const f32,
+        count: ::std::os::raw::c_uint,
+        channels: projectm_channels,
+    );
+}
+extern "C" {
+    pub fn projectm_pcm_add_int16(
+        instance: projectm_handle,
+        samples: *const i16,
+        count: ::std::os::raw::c_uint,
+        channels: projectm_channels,
+    );
+}
+extern "C" {
+    pub fn projectm_pcm_add_uint8(
+        instance: projectm_handle,
+        samples: *const u8,
+        count: ::std::os::raw::c_uint,
+        channels: projectm_channels,
+    );
+}
+pub type projectm_preset_switch_requested_event = ::std::option::Option<
+    unsafe extern "C" fn(is_hard_cut: bool, user_data: *mut ::std::os::raw::c_void),
+>;
+pub type projectm_preset_switch_failed_event = ::std::option::Option<
+    unsafe extern "C" fn(
+        preset_filename: *const ::std::os::raw::c_char,
+        message: *const ::std::os::raw::c_char,
+        user_data: *mut ::std::os::raw::c_void,
+    ),
+>;
+extern "C" {
+    pub fn projectm_set_preset_switch_requested_event_callback(
+        instance: projectm_handle,
+        callback: projectm_preset_switch_requested_event,
+        user_data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn projectm_set_preset_switch_failed_event_callback(
+        instance: projectm_handle,
+        callback: projectm_preset_switch_failed_event,
+        user_data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn projectm_create() -> projectm_handle;
+}
+extern "C" {
+    pub fn projectm_destroy(instance: projectm_handle);
+}
+extern "C" {
+    pub fn projectm_load_preset_file(
+        instance: projectm_handle,
+        filename: *const ::std::os::raw::c_char,
+        smooth_transition: bool,
+    );
+}
+extern "C" {
+    pub fn projectm_load_preset_data(
+        instance: projectm_handle,
+        data: *const ::std::os::raw::c_char,
+        smooth_transition: bool,
+    );
+}
+extern "C" {
+    pub fn projectm_reset_textures(instance: projectm_handle);
+}
+extern "C" {
+    pub fn projectm_get_version_components(
+        major: *mut ::std::os::raw::c_int,
+        minor: *mut ::std::os::raw::c_int,
+        patch: *mut ::std::os::raw::c_int,
+    );
+}
+extern "C" {
+    pub fn projectm_get_version_string() -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_get_vcs_version_string() -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_write_debug_image_on_next_frame(
+        instance: projectm_handle,
+        output_file: *const ::std::os::raw::c_char,
+    );
+}
+extern "C" {
+    pub fn projectm_alloc_string(length: ::std::os::raw::c_uint) -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_free_string(str_: *const ::std::os::raw::c_char);
+}
+extern "C" {
+    pub fn projectm_set_texture_search_paths(
+        instance: projectm_handle,
+        texture_search_paths: *mut *const ::std::os::raw::c_char,
+        count: usize,
+    );
+}
+extern "C" {
+    pub fn projectm_get_beat_sensitivity(instance: projectm_handle) -> f32;
+}
+extern "C" {
+    pub fn projectm_set_beat_sensitivity(instance: projectm_handle, sensitivity: f32);
+}
+extern "C" {
+    pub fn projectm_get_hard_cut_duration(instance: projectm_handle) -> f64;
+}
+extern "C" {
+    pub fn projectm_set_hard_cut_duration(instance: projectm_handle, seconds: f64);
+}
+extern "C" {
+    pub fn projectm_get_hard_cut_enabled(instance: projectm_handle) -> bool;
+}
+extern "C" {
+    pub fn projectm_set_hard_cut_enabled(instance: projectm_handle, enabled: bool);
+}
+extern "C" {
+    pub fn projectm_get_hard_cut_sensitivity(instance: projectm_handle) -> f32;
+}
+extern "C" {
+    pub fn projectm_set_hard_cut_sensitivity(instance: projectm_handle, sensitivity: f32);
+}
+extern "C" {
+    pub fn projectm_get_soft_cut_duration(instance: projectm_handle) -> f64;
+}
+extern "C" {
+    pub fn projectm_set_soft_cut_duration(instance: projectm_handle, seconds: f64);
+}
+extern "C" {
+    pub fn projectm_get_preset_duration(instance: projectm_handle) -> f64;
+}
+extern "C" {
+    pub fn projectm_set_preset_duration(instance: projectm_handle, seconds: f64);
+}
+extern "C" {
+    pub fn projectm_get_mesh_size(instance: projectm_handle, width: *mut usize, height: *mut usize);
+}
+extern "C" {
+    pub fn projectm_set_mesh_size(instance: projectm_handle, width: usize, height: usize);
+}
+extern "C" {
+    pub fn projectm_get_fps(instance: projectm_handle) -> i32;
+}
+extern "C" {
+    pub fn projectm_set_fps(instance: projectm_handle, fps: i32);
+}
+extern "C" {
+    pub fn projectm_get_aspect_correction(instance: projectm_handle) -> bool;
+}
+extern "C" {
+    pub fn projectm_set_aspect_correction(instance: projectm_handle, enabled: bool);
+}
+extern "C" {
+    pub fn projectm_get_easter_egg(instance: projectm_handle) -> f32;
+}
+extern "C" {
+    pub fn projectm_set_easter_egg(instance: projectm_handle, sensitivity: f32);
+}
+extern "C" {
+    pub fn projectm_get_preset_locked(instance: projectm_handle) -> bool;
+}
+extern "C" {
+    pub fn projectm_set_preset_locked(instance: projectm_handle, lock: bool);
+}
+extern "C" {
+    pub fn projectm_get_window_size(
+        instance: projectm_handle,
+        width: *mut usize,
+        height: *mut usize,
+    );
+}
+extern "C" {
+    pub fn projectm_set_window_size(instance: projectm_handle, width: usize, height: usize);
+}
+extern "C" {
+    pub fn projectm_set_frame_time(instance: projectm_handle, seconds_since_first_frame: f64);
+}
+extern "C" {
+    pub fn projectm_get_last_frame_time(instance: projectm_handle) -> f64;
+}
+extern "C" {
+    pub fn projectm_opengl_render_frame(instance: projectm_handle);
+}
+extern "C" {
+    pub fn projectm_opengl_render_frame_fbo(instance: projectm_handle, framebuffer_object_id: u32);
+}
+extern "C" {
+    pub fn projectm_touch(
+        instance: projectm_handle,
+        x: f32,
+        y: f32,
+        pressure: ::std::os::raw::c_int,
+        touch_type: projectm_touch_type,
+    );
+}
+extern "C" {
+    pub fn projectm_touch_drag(
+        instance: projectm_handle,
+        x: f32,
+        y: f32,
+        pressure: ::std::os::raw::c_int,
+    );
+}
+extern "C" {
+    pub fn projectm_touch_destroy(instance: projectm_handle, x: f32, y: f32);
+}
+extern "C" {
+    pub fn projectm_touch_destroy_all(instance: projectm_handle);
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct projectm_playlist {
+    _unused: [u8; 0],
+}
+pub type projectm_playlist_handle = *mut projectm_playlist;
+pub const projectm_playlist_sort_predicate_SORT_PREDICATE_FULL_PATH:
+    projectm_playlist_sort_predicate = 0;
+pub const projectm_playlist_sort_predicate_SORT_PREDICATE_FILENAME_ONLY:
+    projectm_playlist_sort_predicate = 1;
+pub type projectm_playlist_sort_predicate = ::std::os::raw::c_uint;
+pub const projectm_playlist_sort_order_SORT_ORDER_ASCENDING: projectm_playlist_sort_order = 0;
+pub const projectm_playlist_sort_order_SORT_ORDER_DESCENDING: projectm_playlist_sort_order = 1;
+pub type projectm_playlist_sort_order = ::std::os::raw::c_uint;
+pub type projectm_playlist_preset_switched_event = ::std::option::Option<
+    unsafe extern "C" fn(
+        is_hard_cut: bool,
+        index: ::std::os::raw::c_uint,
+        user_data: *mut ::std::os::raw::c_void,
+    ),
+>;
+pub type projectm_playlist_preset_switch_failed_event = ::std::option::Option<
+    unsafe extern "C" fn(
+        preset_filename: *const ::std::os::raw::c_char,
+        message: *const ::std::os::raw::c_char,
+        user_data: *mut ::std::os::raw::c_void,
+    ),
+>;
+extern "C" {
+    pub fn projectm_playlist_free_string_array(array: *mut *mut ::std::os::raw::c_char);
+}
+extern "C" {
+    pub fn projectm_playlist_free_string(string: *mut ::std::os::raw::c_char);
+}
+extern "C" {
+    pub fn projectm_playlist_create(projectm_instance: projectm_handle)
+        -> projectm_playlist_handle;
+}
+extern "C" {
+    pub fn projectm_playlist_destroy(instance: projectm_playlist_handle);
+}
+extern "C" {
+    pub fn projectm_playlist_connect(
+        instance: projectm_playlist_handle,
+        projectm_instance: projectm_handle,
+    );
+}
+extern "C" {
+    pub fn projectm_playlist_size(instance: projectm_playlist_handle) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_clear(instance: projectm_playlist_handle);
+}
+extern "C" {
+    pub fn projectm_playlist_items(
+        instance: projectm_playlist_handle,
+        start: u32,
+        count: u32,
+    ) -> *mut *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_playlist_item(
+        instance: projectm_playlist_handle,
+        index: u32,
+    ) -> *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_playlist_add_path(
+        instance: projectm_playlist_handle,
+        path: *const ::std::os::raw::c_char,
+        recurse_subdirs: bool,
+        allow_duplicates: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_insert_path(
+        instance: projectm_playlist_handle,
+        path: *const ::std::os::raw::c_char,
+        index: u32,
+        recurse_subdirs: bool,
+        allow_duplicates: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_add_preset(
+        instance: projectm_playlist_handle,
+        filename: *const ::std::os::raw::c_char,
+        allow_duplicates: bool,
+    ) -> bool;
+}
+extern "C" {
+    pub fn projectm_playlist_insert_preset(
+        instance: projectm_playlist_handle,
+        filename: *const ::std::os::raw::c_char,
+        index: u32,
+        allow_duplicates: bool,
+    ) -> bool;
+}
+extern "C" {
+    pub fn projectm_playlist_add_presets(
+        instance: projectm_playlist_handle,
+        filenames: *mut *const ::std::os::raw::c_char,
+        count: u32,
+        allow_duplicates: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_insert_presets(
+        instance: projectm_playlist_handle,
+        filenames: *mut *const ::std::os::raw::c_char,
+        count: u32,
+        index: ::std::os::raw::c_uint,
+        allow_duplicates: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_remove_preset(instance: projectm_playlist_handle, index: u32) -> bool;
+}
+extern "C" {
+    pub fn projectm_playlist_remove_presets(
+        instance: projectm_playlist_handle,
+        index: u32,
+        count: u32,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_get_shuffle(instance: projectm_playlist_handle) -> bool;
+}
+extern "C" {
+    pub fn projectm_playlist_set_shuffle(instance: projectm_playlist_handle, shuffle: bool);
+}
+extern "C" {
+    pub fn projectm_playlist_get_retry_count(instance: projectm_playlist_handle) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_set_retry_count(instance: projectm_playlist_handle, retry_count: u32);
+}
+extern "C" {
+    pub fn projectm_playlist_sort(
+        instance: projectm_playlist_handle,
+        start_index: u32,
+        count: u32,
+        predicate: projectm_playlist_sort_predicate,
+        order: projectm_playlist_sort_order,
+    );
+}
+extern "C" {
+    pub fn projectm_playlist_set_preset_switched_event_callback(
+        instance: projectm_playlist_handle,
+        callback: projectm_playlist_preset_switched_event,
+        user_data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn projectm_playlist_set_preset_switch_failed_event_callback(
+        instance: projectm_playlist_handle,
+        callback: projectm_playlist_preset_switch_failed_event,
+        user_data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn projectm_playlist_get_position(instance: projectm_playlist_handle) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_set_position(
+        instance: projectm_playlist_handle,
+        new_position: u32,
+        hard_cut: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_play_next(instance: projectm_playlist_handle, hard_cut: bool) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_play_previous(
+        instance: projectm_playlist_handle,
+        hard_cut: bool,
+    ) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_play_last(instance: projectm_playlist_handle, hard_cut: bool) -> u32;
+}
+extern "C" {
+    pub fn projectm_playlist_set_filter(
+        instance: projectm_playlist_handle,
+        filter_list: *mut *const ::std::os::raw::c_char,
+        count: usize,
+    );
+}
+extern "C" {
+    pub fn projectm_playlist_get_filter(
+        instance: projectm_playlist_handle,
+        count: *mut usize,
+    ) -> *mut *mut ::std::os::raw::c_char;
+}
+extern "C" {
+    pub fn projectm_playlist_apply_filter(instance: projectm_playlist_handle) -> usize;
+}
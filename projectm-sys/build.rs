@@ -1,18 +1,68 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::{env, path::Path, process::Command};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 mod build_bindgen;
-use crate::build_bindgen::bindgen;
+mod build_system;
+use crate::build_bindgen::bindings;
+
+/// libprojectM release to build, selected by the `projectm-4-*` features.
+const PROJECTM_VERSION: &str = if cfg!(feature = "projectm-4-1") {
+    "4.1.0"
+} else {
+    "4.0.0"
+};
 
 lazy_static! {
     static ref PROJECTM_BUILD: String = format!("{}/projectm", env::var("OUT_DIR").unwrap());
 }
 
-fn main() {
-    if !Path::new(PROJECTM_BUILD.as_str()).exists() {
-        let _ = Command::new("git")
+/// Locate the libprojectM sources to build.
+///
+/// In order of preference: the directory named by `PROJECTM_SRC_DIR`, the
+/// sources packaged with the crate (`vendored` feature), or a fresh clone of
+/// the release tag.
+fn source_dir() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=PROJECTM_SRC_DIR");
+
+    if let Some(dir) = env::var_os("PROJECTM_SRC_DIR") {
+        let dir = PathBuf::from(dir);
+        if !is_source_dir(&dir) {
+            panic!(
+                "PROJECTM_SRC_DIR is set to {}, which does not contain the libprojectM sources \
+                 (no CMakeLists.txt found)",
+                dir.display()
+            );
+        }
+        return dir;
+    }
+
+    if cfg!(feature = "vendored") {
+        let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("libprojectM");
+        if !is_source_dir(&dir) {
+            panic!(
+                "the `vendored` feature is enabled, but {} does not contain the libprojectM \
+                 sources. Fetch them with\n\n    git clone --recurse-submodules --depth=1 \
+                 --branch v{PROJECTM_VERSION} https://github.com/projectM-visualizer/projectm.git \
+                 {}\n\nor point PROJECTM_SRC_DIR at an existing checkout.",
+                dir.display(),
+                dir.display()
+            );
+        }
+        return dir;
+    }
+
+    let dir = PathBuf::from(PROJECTM_BUILD.as_str());
+    if !is_source_dir(&dir) {
+        // Remove whatever an earlier, interrupted clone left behind.
+        let _ = fs::remove_dir_all(&dir);
+
+        let status = Command::new("git")
             .args([
                 "-c",
                 "advice.detachedHead=false",
@@ -20,109 +70,178 @@ fn main() {
                 "--recurse-submodules",
                 "--depth=1",
                 "--branch",
-                "v4.0.0",
+                &format!("v{PROJECTM_VERSION}"),
                 "https://github.com/projectM-visualizer/projectm.git",
                 &PROJECTM_BUILD,
             ])
             .status();
-    }
 
-    // Feature: enable-playlist
-    fn enable_playlist() -> String {
-        if cfg!(feature = "playlist") {
-            "ON".to_string()
-        } else {
-            "OFF".to_string()
+        match status {
+            Ok(status) if status.success() && is_source_dir(&dir) => {}
+            result => {
+                let reason = match result {
+                    Ok(status) => format!("git exited with {status}"),
+                    Err(err) => format!("git could not be run: {err}"),
+                };
+                panic!(
+                    "failed to download the libprojectM v{PROJECTM_VERSION} sources ({reason}). \
+                     For offline builds, enable the `vendored` feature or set PROJECTM_SRC_DIR \
+                     to a libprojectM checkout."
+                );
+            }
         }
     }
+    dir
+}
 
-    #[cfg(target_os = "windows")]
-    let dst = cmake::Config::new(PROJECTM_BUILD.as_str())
-        .generator("Visual Studio 17 2022")
-        .define(
-            "CMAKE_TOOLCHAIN_FILE",
-            format!(
-                "{}/scripts/buildsystems/vcpkg.cmake",
-                env::var("VCPKG_INSTALLATION_ROOT").unwrap()
-            ),
-        )
-        .define("VCPKG_TARGET_TRIPLET", "x64-windows-static-md")
-        .define(
-            "CMAKE_MSVC_RUNTIME_LIBRARY",
-            "MultiThreaded$<$<CONFIG:Debug>:Debug>DLL",
-        )
-        .define("ENABLE_PLAYLIST", enable_playlist().as_str())
-        .build();
-
-    #[cfg(target_os = "linux")]
-    let dst = cmake::Config::new(PROJECTM_BUILD.as_str())
-        .define("ENABLE_PLAYLIST", enable_playlist().as_str())
-        .build();
-
-    #[cfg(target_os = "macos")]
-    let dst = cmake::Config::new(PROJECTM_BUILD.as_str())
-        .define("ENABLE_PLAYLIST", enable_playlist().as_str())
-        .build();
-
-    #[cfg(target_os = "emscripten")]
-    let dst = cmake::Config::new(PROJECTM_BUILD.as_str())
-        .define("ENABLE_PLAYLIST", enable_playlist().as_str())
-        .define("ENABLE_EMSCRIPTEN", "ON")
-        .build();
+fn is_source_dir(dir: &Path) -> bool {
+    dir.join("CMakeLists.txt").is_file()
+}
 
-    println!("cargo:rustc-link-search=native={}/lib", dst.display());
+fn main() {
+    let system = build_system::use_system();
+    let (include_dirs, version) = if system {
+        build_system::probe()
+    } else {
+        (
+            vec![build_from_source().join("include")],
+            PROJECTM_VERSION.to_string(),
+        )
+    };
 
-    #[cfg(target_os = "windows")]
-    if Ok("release".to_owned()) == env::var("PROFILE") {
-        println!("cargo:rustc-link-lib=dylib=projectM-4");
+    bindings(&include_dirs, &version, !system)
+}
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
-    } else {
-        println!("cargo:rustc-link-lib=dylib=projectM-4d");
+/// Build libprojectM with CMake and link it. Returns the install prefix.
+fn build_from_source() -> PathBuf {
+    let source_dir = source_dir();
+    // `cfg!(target_os)` would describe the host running this script.
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let link_static = cfg!(feature = "static") || target_os == "emscripten";
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlistd");
+    if cfg!(feature = "static") && !matches!(target_os.as_str(), "linux" | "emscripten") {
+        panic!("the `static` feature is only supported on Linux");
     }
 
-    #[cfg(target_os = "linux")]
-    if Ok("release".to_owned()) == env::var("PROFILE") {
-        println!("cargo:rustc-link-lib=dylib=projectM-4");
+    let mut config = cmake::Config::new(&source_dir);
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
-    } else {
-        println!("cargo:rustc-link-lib=dylib=projectM-4d");
+    // Feature: enable-playlist
+    config.define(
+        "ENABLE_PLAYLIST",
+        if cfg!(feature = "playlist") {
+            "ON"
+        } else {
+            "OFF"
+        },
+    );
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlistd");
+    println!("cargo:rerun-if-env-changed=PROJECTM_CMAKE_BUILD_TYPE");
+    if let Ok(build_type) = env::var("PROJECTM_CMAKE_BUILD_TYPE") {
+        config.profile(&build_type);
     }
 
-    #[cfg(target_os = "macos")]
-    if Ok("release".to_owned()) == env::var("PROFILE") {
-        println!("cargo:rustc-link-lib=dylib=projectM-4");
-
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
-    } else {
-        println!("cargo:rustc-link-lib=dylib=projectM-4d");
-
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlistd");
+    match target_os.as_str() {
+        "windows" => {
+            config
+                .generator("Visual Studio 17 2022")
+                .define(
+                    "CMAKE_TOOLCHAIN_FILE",
+                    format!(
+                        "{}/scripts/buildsystems/vcpkg.cmake",
+                        env::var("VCPKG_INSTALLATION_ROOT").unwrap()
+                    ),
+                )
+                .define("VCPKG_TARGET_TRIPLET", "x64-windows-static-md")
+                .define(
+                    "CMAKE_MSVC_RUNTIME_LIBRARY",
+                    "MultiThreaded$<$<CONFIG:Debug>:Debug>DLL",
+                );
+        }
+        "linux" => {
+            config.define("BUILD_SHARED_LIBS", if link_static { "OFF" } else { "ON" });
+        }
+        "emscripten" => {
+            config.define("ENABLE_EMSCRIPTEN", "ON");
+        }
+        _ => {}
     }
 
-    #[cfg(target_os = "emscripten")]
-    if Ok("release".to_owned()) == env::var("PROFILE") {
-        println!("cargo:rustc-link-lib=static=projectM-4");
+    let dst = config.build();
+
+    let lib_dir = ["lib", "lib64"]
+        .iter()
+        .map(|dir| dst.join(dir))
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| panic!("no library directory in {}", dst.display()));
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+
+    // libprojectM only adds its `d` postfix to Debug builds.
+    let debug_postfix = match config.get_profile() {
+        "Debug" => Some(true),
+        "Release" | "RelWithDebInfo" | "MinSizeRel" => Some(false),
+        _ => None,
+    };
+
+    // The playlist library depends on libprojectM, so it goes first.
+    #[cfg(feature = "playlist")]
+    link_library(&lib_dir, "projectM-4-playlist", link_static, debug_postfix);
+    link_library(&lib_dir, "projectM-4", link_static, debug_postfix);
+
+    if link_static && target_os == "linux" {
+        println!("cargo:rustc-link-lib=dylib=stdc++");
+        println!("cargo:rustc-link-lib=dylib=GL");
+    }
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlist");
-    } else {
-        println!("cargo:rustc-link-lib=static=projectM-4d");
+    dst
+}
 
-        #[cfg(feature = "playlist")]
-        println!("cargo:rustc-link-lib=dylib=projectM-4-playlistd");
+/// Find the library built for `name` in `lib_dir` and link it.
+///
+/// Debug builds of libprojectM carry a `d` suffix. `debug_postfix` says
+/// whether to expect it; for a custom build type it is `None`, and whichever
+/// variant was installed is linked, as long as there is only one.
+fn link_library(lib_dir: &Path, name: &str, link_static: bool, debug_postfix: Option<bool>) {
+    let variants = match debug_postfix {
+        Some(true) => vec![format!("{name}d")],
+        Some(false) => vec![name.to_string()],
+        None => vec![name.to_string(), format!("{name}d")],
+    };
+
+    let mut found: Vec<(String, String, &str)> = Vec::new();
+    for variant in variants {
+        let candidates = [
+            (format!("lib{variant}.a"), "static"),
+            (format!("lib{variant}.so"), "dylib"),
+            (format!("lib{variant}.dylib"), "dylib"),
+            (
+                format!("{variant}.lib"),
+                if link_static { "static" } else { "dylib" },
+            ),
+        ];
+
+        // Shared and static builds may have been installed side by side.
+        let candidate = candidates
+            .into_iter()
+            .filter(|(_, kind)| link_static == (*kind == "static"))
+            .find(|(file, _)| lib_dir.join(file).is_file());
+        if let Some((file, kind)) = candidate {
+            found.push((file, variant, kind));
+        }
     }
 
-    bindgen()
+    match found.as_slice() {
+        [(_, variant, kind)] => println!("cargo:rustc-link-lib={kind}={variant}"),
+        [] => panic!(
+            "could not find the {} {name} library in {}",
+            if link_static { "static" } else { "shared" },
+            lib_dir.display()
+        ),
+        [(release, ..), (debug, ..)] => panic!(
+            "both {release} and {debug} exist in {}, and the custom CMake build type does not \
+             say which to link. Remove the stale one or set PROJECTM_CMAKE_BUILD_TYPE to a \
+             standard build type.",
+            lib_dir.display()
+        ),
+        _ => unreachable!("at most one release and one debug variant"),
+    }
 }
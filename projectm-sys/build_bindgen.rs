@@ -1,28 +1,75 @@
 use std::{env, path::PathBuf};
 
-pub fn bindgen() {
-    println!("cargo:rerun-if-changed=wrapper.h");
+/// libprojectM version the checked-in bindings were generated from. They
+/// only use API present since this release, so they work with any later 4.x.
+const PREGENERATED_VERSION: &str = if cfg!(feature = "projectm-4-1") {
+    "4.1.0"
+} else {
+    "4.0.0"
+};
 
+fn header_name() -> &'static str {
+    if cfg!(feature = "playlist") {
+        "playlist"
+    } else {
+        "default"
+    }
+}
+
+/// Path of the checked-in bindings matching the enabled features.
+fn pregenerated_bindings() -> PathBuf {
+    PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("bindings")
+        .join(format!("projectm-{PREGENERATED_VERSION}"))
+        .join(format!("{}.rs", header_name()))
+}
+
+/// Select the bindings compiled into the crate.
+#[cfg(not(feature = "bindgen"))]
+pub fn bindings(_include_dirs: &[PathBuf], _version: &str, _from_source: bool) {
+    let path = pregenerated_bindings();
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rustc-env=PROJECTM_SYS_BINDINGS={}", path.display());
+    println!("cargo:rustc-env=PROJECTM_SYS_BINDINGS_VERSION={PREGENERATED_VERSION}");
+}
+
+/// Generate bindings from the libprojectM headers in `include_dirs`.
+///
+/// `from_source` is set when the headers come from the libprojectM release
+/// the checked-in bindings were generated from, rather than from a system
+/// install of whichever version.
+#[cfg(feature = "bindgen")]
+pub fn bindings(include_dirs: &[PathBuf], version: &str, from_source: bool) {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let out_path = out_dir.join("bindings.rs");
 
-    fn get_header() -> String {
-        if cfg!(feature = "playlist") {
-            "bindgen/playlist.h".to_string()
-        } else {
-            "bindgen/default.h".to_string()
-        }
-    }
-
     let bindings = bindgen::Builder::default()
-        .header(get_header())
+        .header(format!("bindgen/{}.h", header_name()))
         .allowlist_function("projectm_.*")
-        .clang_arg(format!("-I{}/include", out_dir.display()))
+        .clang_args(
+            include_dirs
+                .iter()
+                .map(|dir| format!("-I{}", dir.display())),
+        )
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
         .generate()
         .expect("Unable to generate bindings");
 
     bindings
-        .write_to_file(out_path)
+        .write_to_file(&out_path)
         .expect("Couldn't write bindings!");
+
+    println!(
+        "cargo:rustc-env=PROJECTM_SYS_BINDINGS={}",
+        out_path.display()
+    );
+    println!("cargo:rustc-env=PROJECTM_SYS_BINDINGS_VERSION={version}");
+    // Lets the drift test find the checked-in copy. System headers may be
+    // a different or patched release, so there is nothing to compare with.
+    if from_source {
+        println!(
+            "cargo:rustc-env=PROJECTM_SYS_PREGENERATED_BINDINGS={}",
+            pregenerated_bindings().display()
+        );
+    }
 }
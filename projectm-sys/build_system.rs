@@ -0,0 +1,50 @@
+use std::{env, path::PathBuf};
+
+/// Range of libprojectM versions the bindings are compatible with.
+const MIN_VERSION: &str = if cfg!(feature = "projectm-4-1") {
+    "4.1.0"
+} else {
+    "4.0.0"
+};
+const MAX_VERSION: &str = "5.0.0";
+
+/// Whether to link against a system-installed libprojectM instead of
+/// building it from source.
+pub fn use_system() -> bool {
+    println!("cargo:rerun-if-env-changed=PROJECTM_USE_SYSTEM");
+
+    cfg!(feature = "system") || env::var("PROJECTM_USE_SYSTEM").map_or(false, |value| value == "1")
+}
+
+/// Find libprojectM with pkg-config and emit its link flags.
+///
+/// Returns the include directories to generate bindings from and the
+/// version found.
+pub fn probe() -> (Vec<PathBuf>, String) {
+    let library = probe_library("projectM-4");
+    let mut include_dirs = library.include_paths;
+
+    if cfg!(feature = "playlist") {
+        include_dirs.extend(probe_library("projectM-4-playlist").include_paths);
+    }
+
+    include_dirs.sort();
+    include_dirs.dedup();
+    (include_dirs, library.version)
+}
+
+fn probe_library(name: &str) -> pkg_config::Library {
+    match pkg_config::Config::new()
+        .range_version(MIN_VERSION..MAX_VERSION)
+        .statik(cfg!(feature = "static"))
+        .probe(name)
+    {
+        Ok(library) => library,
+        Err(err) => panic!(
+            "could not find a system libprojectM ({name} >= {MIN_VERSION}, < {MAX_VERSION}): \
+             {err}\n\nInstall the libprojectM development files and make sure pkg-config can \
+             find them (e.g. via PKG_CONFIG_PATH), or build libprojectM from source by \
+             disabling the `system` feature and unsetting PROJECTM_USE_SYSTEM."
+        ),
+    }
+}
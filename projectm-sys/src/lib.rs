@@ -2,4 +2,9 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+// Either the pre-generated bindings or, with the `bindgen` feature, freshly
+// generated ones. Selected by the build script.
+include!(env!("PROJECTM_SYS_BINDINGS"));
+
+/// Version of the libprojectM headers the bindings were generated from.
+pub const BINDINGS_VERSION: &str = env!("PROJECTM_SYS_BINDINGS_VERSION");
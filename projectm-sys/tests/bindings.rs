@@ -0,0 +1,28 @@
+#[cfg(test)]
+mod bindings {
+    /// The checked-in bindings must match what bindgen generates from the
+    /// headers. Set `PROJECTM_UPDATE_BINDINGS=1` to overwrite them instead.
+    ///
+    /// Only checked when libprojectM is built from source, as system headers
+    /// need not be the release the bindings were generated from.
+    #[cfg(feature = "bindgen")]
+    #[test]
+    fn pregenerated_bindings_are_up_to_date() {
+        let generated = include_str!(env!("PROJECTM_SYS_BINDINGS"));
+        let Some(path) = option_env!("PROJECTM_SYS_PREGENERATED_BINDINGS") else {
+            return;
+        };
+
+        if std::env::var_os("PROJECTM_UPDATE_BINDINGS").is_some() {
+            std::fs::write(path, generated).unwrap();
+            return;
+        }
+
+        let checked_in = std::fs::read_to_string(path).unwrap();
+        assert!(
+            generated == checked_in,
+            "{path} is out of date, regenerate it with \
+             `PROJECTM_UPDATE_BINDINGS=1 cargo test --features bindgen`"
+        );
+    }
+}
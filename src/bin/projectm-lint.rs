@@ -0,0 +1,182 @@
+//! Check `.milk` presets for common mistakes.
+//!
+//! ```text
+//! projectm-lint [--format human|json] [--deny-warnings] <PATH>...
+//! ```
+//!
+//! Directories are searched recursively for `.milk` files. The exit status
+//! is 0 when no errors were found (and no warnings, with
+//! `--deny-warnings`), 1 when some were, and 2 on bad usage or when a file
+//! could not be read.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use projectm::preset;
+use projectm::preset::lint::{lint_source, Diagnostic, Severity};
+
+const USAGE: &str = "usage: projectm-lint [--format human|json] [--deny-warnings] <PATH>...";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+}
+
+struct Options {
+    format: Format,
+    deny_warnings: bool,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        format: Format::Human,
+        deny_warnings: false,
+        paths: Vec::new(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(String::new()),
+            "--deny-warnings" => options.deny_warnings = true,
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("human") => Format::Human,
+                    Some("json") => Format::Json,
+                    Some(other) => return Err(format!("unknown format `{other}`")),
+                    None => return Err("`--format` needs a value".to_string()),
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => options.paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if options.paths.is_empty() {
+        return Err("no presets given".to_string());
+    }
+    Ok(options)
+}
+
+/// `path` itself, or every `.milk` file below it if it is a directory.
+fn collect_presets(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            collect_presets(&entry, files)?;
+        } else if entry.extension().map_or(false, |ext| ext == "milk") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_diagnostic(path: &str, source: &str, diagnostic: &Diagnostic) -> String {
+    let (start, end) = diagnostic.location(source);
+    format!(
+        "{{\"file\":{},\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\
+         \"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+        json_string(path),
+        diagnostic.severity,
+        diagnostic.code,
+        json_string(&diagnostic.message),
+        start.line,
+        start.column,
+        end.line,
+        end.column,
+    )
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) if message.is_empty() => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("projectm-lint: {message}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut failed_to_read = false;
+    let mut files = Vec::new();
+    for path in &options.paths {
+        if let Err(err) = collect_presets(path, &mut files) {
+            eprintln!("projectm-lint: {}: {err}", path.display());
+            failed_to_read = true;
+        }
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    let mut json = Vec::new();
+    for file in &files {
+        let source = match std::fs::read(file) {
+            Ok(bytes) => preset::decode(&bytes),
+            Err(err) => {
+                eprintln!("projectm-lint: {}: {err}", file.display());
+                failed_to_read = true;
+                continue;
+            }
+        };
+
+        let path = file.display().to_string();
+        for diagnostic in lint_source(&source) {
+            match diagnostic.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+            match options.format {
+                Format::Human => println!("{}", diagnostic.render(&path, &source)),
+                Format::Json => json.push(json_diagnostic(&path, &source, &diagnostic)),
+            }
+        }
+    }
+
+    match options.format {
+        Format::Human => eprintln!(
+            "checked {} preset(s): {errors} error(s), {warnings} warning(s)",
+            files.len()
+        ),
+        Format::Json => println!("[{}]", json.join(",")),
+    }
+
+    if failed_to_read {
+        ExitCode::from(2)
+    } else if errors > 0 || (options.deny_warnings && warnings > 0) {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,55 @@
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Storage for a user callback registered with the native library.
+///
+/// The slot is kept at a stable address and handed to projectM as the
+/// callback's `user_data`. While the callback runs it is taken out of the
+/// slot, so it may replace or clear itself without freeing the closure
+/// that is currently executing.
+pub(crate) struct CallbackSlot<F: ?Sized> {
+    callback: RefCell<Option<Box<F>>>,
+    generation: Cell<u64>,
+}
+
+impl<F: ?Sized> CallbackSlot<F> {
+    pub(crate) fn new() -> Self {
+        CallbackSlot {
+            callback: RefCell::new(None),
+            generation: Cell::new(0),
+        }
+    }
+
+    /// Store a new callback, dropping the previous one.
+    pub(crate) fn set(&self, callback: Box<F>) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        let previous = self.callback.replace(Some(callback));
+        drop(previous);
+    }
+
+    /// Remove and drop the current callback.
+    pub(crate) fn clear(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        let previous = self.callback.take();
+        drop(previous);
+    }
+
+    /// Invoke the stored callback, if any.
+    ///
+    /// Panics are caught here so they never unwind into C++.
+    pub(crate) fn call(&self, invoke: impl FnOnce(&mut F)) {
+        let callback = self.callback.borrow_mut().take();
+        let Some(mut callback) = callback else {
+            return;
+        };
+
+        let generation = self.generation.get();
+        let _ = catch_unwind(AssertUnwindSafe(|| invoke(&mut callback)));
+
+        // Only put the callback back if it wasn't replaced or cleared while
+        // it was running.
+        if self.generation.get() == generation {
+            *self.callback.borrow_mut() = Some(callback);
+        }
+    }
+}
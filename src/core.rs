@@ -16,25 +16,171 @@ extern crate libc;
 extern crate projectm_sys as ffi;
 
 use std::cell::RefCell;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::path::Path;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::mpsc::Receiver;
+
+use crate::callback::CallbackSlot;
+use crate::error::{Error, Result};
+#[cfg(feature = "stream")]
+use crate::event::EventStream;
+use crate::event::{Event, EventSenders};
+use crate::pcm::{PcmReceiver, PcmSender, Sample};
+use crate::settings::{ProjectMBuilder, ProjectMSettings};
+use crate::version::Version;
 
 pub type ProjectMHandle = *mut ffi::projectm;
 
-pub type ProjectMChannels = u32;
-pub const MONO: ProjectMChannels = 1;
-pub const STEREO: ProjectMChannels = 2;
-
-pub type ProjectMTouchType = u32;
-pub const TOUCH_TYPE_RANDOM: ProjectMTouchType = 0;
-pub const TOUCH_TYPE_CIRCLE: ProjectMTouchType = 1;
-pub const TOUCH_TYPE_RADIAL_BLOB: ProjectMTouchType = 2;
-pub const TOUCH_TYPE_BLOB2: ProjectMTouchType = 3;
-pub const TOUCH_TYPE_BLOB3: ProjectMTouchType = 4;
-pub const TOUCH_TYPE_DERIVATIVE_LINE: ProjectMTouchType = 5;
-pub const TOUCH_TYPE_BLOB5: ProjectMTouchType = 6;
-pub const TOUCH_TYPE_LINE: ProjectMTouchType = 7;
-pub const TOUCH_TYPE_DOUBLE_LINE: ProjectMTouchType = 8;
+type PresetSwitchRequestedSlot = CallbackSlot<dyn FnMut(bool)>;
+type PresetSwitchFailedSlot = CallbackSlot<dyn FnMut(String, String)>;
+
+/// Channel layout of PCM data passed to projectM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[repr(u32)]
+pub enum Channels {
+    Mono = ffi::projectm_channels_PROJECTM_MONO,
+    Stereo = ffi::projectm_channels_PROJECTM_STEREO,
+}
+
+impl Channels {
+    pub const ALL: [Channels; 2] = [Channels::Mono, Channels::Stereo];
+
+    /// Name used by `Display`, `FromStr` and serde.
+    pub fn name(self) -> &'static str {
+        match self {
+            Channels::Mono => "mono",
+            Channels::Stereo => "stereo",
+        }
+    }
+
+    /// Number of interleaved samples per frame.
+    pub fn count(self) -> usize {
+        self as usize
+    }
+}
+
+impl TryFrom<u32> for Channels {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|channels| *channels as u32 == value)
+            .ok_or(Error::InvalidChannels(value))
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Channels {
+    type Err = Error;
+
+    /// Parse a channel layout by name (`mono`, `stereo`) or channel count.
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(value) = s.parse::<u32>() {
+            return Self::try_from(value);
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|channels| channels.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::UnknownName {
+                kind: "channel layout",
+                name: s.to_owned(),
+            })
+    }
+}
+
+/// Kind of waveform created by [`ProjectM::touch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[repr(u32)]
+pub enum TouchType {
+    Random = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_RANDOM,
+    Circle = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_CIRCLE,
+    RadialBlob = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_RADIAL_BLOB,
+    Blob2 = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB2,
+    Blob3 = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB3,
+    DerivativeLine = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_DERIVATIVE_LINE,
+    Blob5 = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_BLOB5,
+    Line = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_LINE,
+    DoubleLine = ffi::projectm_touch_type_PROJECTM_TOUCH_TYPE_DOUBLE_LINE,
+}
+
+impl TouchType {
+    pub const ALL: [TouchType; 9] = [
+        TouchType::Random,
+        TouchType::Circle,
+        TouchType::RadialBlob,
+        TouchType::Blob2,
+        TouchType::Blob3,
+        TouchType::DerivativeLine,
+        TouchType::Blob5,
+        TouchType::Line,
+        TouchType::DoubleLine,
+    ];
+
+    /// Name used by `Display`, `FromStr` and serde.
+    pub fn name(self) -> &'static str {
+        match self {
+            TouchType::Random => "random",
+            TouchType::Circle => "circle",
+            TouchType::RadialBlob => "radial_blob",
+            TouchType::Blob2 => "blob2",
+            TouchType::Blob3 => "blob3",
+            TouchType::DerivativeLine => "derivative_line",
+            TouchType::Blob5 => "blob5",
+            TouchType::Line => "line",
+            TouchType::DoubleLine => "double_line",
+        }
+    }
+}
+
+impl TryFrom<u32> for TouchType {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|touch_type| *touch_type as u32 == value)
+            .ok_or(Error::InvalidTouchType(value))
+    }
+}
+
+impl fmt::Display for TouchType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TouchType {
+    type Err = Error;
+
+    /// Parse a touch type by name (e.g. `radial_blob`) or numeric value.
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(value) = s.parse::<u32>() {
+            return Self::try_from(value);
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|touch_type| touch_type.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::UnknownName {
+                kind: "touch type",
+                name: s.to_owned(),
+            })
+    }
+}
 
 struct Projectm {}
 
@@ -43,28 +189,26 @@ impl Projectm {
     // Core
     // -----------------
 
-    fn create() -> *mut ffi::projectm {
-        unsafe { ffi::projectm_create() }
+    fn create() -> Result<ProjectMHandle> {
+        let instance = unsafe { ffi::projectm_create() };
+
+        if instance.is_null() {
+            Err(Error::NullInstance)
+        } else {
+            Ok(instance)
+        }
     }
 
     fn destroy(instance: ProjectMHandle) {
         unsafe { ffi::projectm_destroy(instance) };
     }
 
-    fn load_preset_file(instance: ProjectMHandle, filename: &str, smooth_transition: bool) {
-        unsafe {
-            ffi::projectm_load_preset_file(
-                instance,
-                filename.as_ptr() as *mut i8,
-                smooth_transition,
-            )
-        };
+    fn load_preset_file(instance: ProjectMHandle, filename: &CStr, smooth_transition: bool) {
+        unsafe { ffi::projectm_load_preset_file(instance, filename.as_ptr(), smooth_transition) };
     }
 
-    fn load_preset_data(instance: ProjectMHandle, data: &str, smooth_transition: bool) {
-        unsafe {
-            ffi::projectm_load_preset_data(instance, data.as_ptr() as *mut i8, smooth_transition)
-        };
+    fn load_preset_data(instance: ProjectMHandle, data: &CStr, smooth_transition: bool) {
+        unsafe { ffi::projectm_load_preset_data(instance, data.as_ptr(), smooth_transition) };
     }
 
     fn reset_textures(instance: ProjectMHandle) {
@@ -93,74 +237,79 @@ impl Projectm {
         (version.major, version.minor, version.patch)
     }
 
-    fn get_version_string() -> String {
+    fn get_version_string() -> Result<String> {
         let get_version = unsafe { ffi::projectm_get_version_string() };
-        let version_str = unsafe { std::ffi::CStr::from_ptr(get_version) };
-        let version_str_slice = version_str.to_str().unwrap();
-        let version = version_str_slice.to_owned();
+        let version_str = unsafe { CStr::from_ptr(get_version) };
+        let version = version_str.to_str().map(str::to_owned);
 
         unsafe { ffi::projectm_free_string(get_version) };
 
-        version
+        Ok(version?)
     }
 
-    fn get_vcs_version_string() -> String {
+    fn get_vcs_version_string() -> Result<String> {
         let get_vcs_version = unsafe { ffi::projectm_get_vcs_version_string() };
-        let vcs_version_str = unsafe { std::ffi::CStr::from_ptr(get_vcs_version) };
-        let vcs_version_str_slice = vcs_version_str.to_str().unwrap();
-        let vcs_version = vcs_version_str_slice.to_owned();
+        let vcs_version_str = unsafe { CStr::from_ptr(get_vcs_version) };
+        let vcs_version = vcs_version_str.to_str().map(str::to_owned);
 
         unsafe { ffi::projectm_free_string(get_vcs_version) };
 
-        vcs_version
+        Ok(vcs_version?)
     }
 
     // -----------------
     // Callbacks
     // -----------------
 
-    fn set_preset_switch_requested_event_callback<F: FnMut(bool)>(
-        instance: ProjectMHandle,
-        callback: F,
-    ) {
-        unsafe extern "C" fn trampoline<F: FnMut(bool)>(
-            is_hard_cut: bool,
-            user_data: *mut std::os::raw::c_void,
-        ) {
-            unsafe { (*user_data.cast::<F>())(is_hard_cut) }
+    fn set_preset_switch_requested_event_callback(instance: ProjectMHandle, callbacks: &Callbacks) {
+        unsafe extern "C" fn trampoline(is_hard_cut: bool, user_data: *mut std::os::raw::c_void) {
+            let callbacks = unsafe { &*user_data.cast::<Callbacks>() };
+            callbacks.events.send(Event::PresetSwitchRequested {
+                hard_cut: is_hard_cut,
+            });
+            callbacks
+                .preset_switch_requested
+                .call(|callback| callback(is_hard_cut));
         }
         unsafe {
             ffi::projectm_set_preset_switch_requested_event_callback(
                 instance,
-                Some(trampoline::<F>),
-                (Box::leak(Box::new(callback)) as *mut F).cast::<std::os::raw::c_void>(),
+                Some(trampoline),
+                (callbacks as *const Callbacks as *mut Callbacks).cast::<std::os::raw::c_void>(),
             )
         }
     }
 
-    fn set_preset_switch_failed_event_callback<F: FnMut(String, String)>(
-        instance: ProjectMHandle,
-        callback: F,
-    ) {
-        unsafe extern "C" fn trampoline<F: FnMut(String, String)>(
-            preset_filename: *const i8,
-            message: *const i8,
+    fn set_preset_switch_failed_event_callback(instance: ProjectMHandle, callbacks: &Callbacks) {
+        unsafe extern "C" fn trampoline(
+            preset_filename: *const std::os::raw::c_char,
+            message: *const std::os::raw::c_char,
             user_data: *mut std::os::raw::c_void,
         ) {
-            let preset_filename_str = unsafe { std::ffi::CStr::from_ptr(preset_filename) };
-            let preset_filename_str_slice = preset_filename_str.to_str().unwrap();
-            let preset_filename = preset_filename_str_slice.to_owned();
-
-            let message_str = unsafe { std::ffi::CStr::from_ptr(message) };
-            let message_str_slice = message_str.to_str().unwrap();
-            let message = message_str_slice.to_owned();
-            unsafe { (*user_data.cast::<F>())(preset_filename, message) }
+            let callbacks = unsafe { &*user_data.cast::<Callbacks>() };
+
+            // There is no way to report an error back through the callback, so
+            // invalid UTF-8 is replaced rather than dropping the event.
+            let preset_filename = unsafe { CStr::from_ptr(preset_filename) }
+                .to_string_lossy()
+                .into_owned();
+            let message = unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned();
+
+            callbacks.events.send(Event::PresetSwitchFailed {
+                filename: preset_filename.clone(),
+                message: message.clone(),
+            });
+            callbacks
+                .preset_switch_failed
+                .call(|callback| callback(preset_filename, message));
         }
         unsafe {
             ffi::projectm_set_preset_switch_failed_event_callback(
                 instance,
-                Some(trampoline::<F>),
-                (Box::leak(Box::new(callback)) as *mut F).cast::<std::os::raw::c_void>(),
+                Some(trampoline),
+                (callbacks as *const Callbacks as *mut Callbacks).cast::<std::os::raw::c_void>(),
             )
         }
     }
@@ -169,16 +318,7 @@ impl Projectm {
     // Parameters
     // -----------------
 
-    fn set_texture_search_paths(
-        instance: ProjectMHandle,
-        texture_search_paths: &[String],
-        count: usize,
-    ) {
-        let texture_search_paths_cstr: Vec<_> = texture_search_paths
-            .iter()
-            .map(|arg| CString::new(arg.as_str()).unwrap())
-            .collect();
-
+    fn set_texture_search_paths(instance: ProjectMHandle, texture_search_paths_cstr: &[CString]) {
         let mut texture_search_paths_pointer: Vec<_> = texture_search_paths_cstr
             .iter()
             .map(|arg| arg.as_ptr())
@@ -190,7 +330,7 @@ impl Projectm {
             ffi::projectm_set_texture_search_paths(
                 instance,
                 texture_search_paths_pointer.as_ptr() as *mut *const ::std::os::raw::c_char,
-                count,
+                texture_search_paths_cstr.len(),
             )
         };
     }
@@ -270,13 +410,13 @@ impl Projectm {
         }
     }
 
-    fn get_fps(instance: ProjectMHandle) -> u32 {
-        unsafe { ffi::projectm_get_fps(instance).try_into().unwrap() }
+    fn get_fps(instance: ProjectMHandle) -> Result<u32> {
+        let fps = unsafe { ffi::projectm_get_fps(instance) };
+        u32::try_from(fps).map_err(|_| Error::OutOfRange("fps"))
     }
 
-    // FIXME: shouldn't it also be a usize?
-    fn set_fps(instance: ProjectMHandle, fps: u32) {
-        unsafe { ffi::projectm_set_fps(instance, fps as i32) };
+    fn set_fps(instance: ProjectMHandle, fps: i32) {
+        unsafe { ffi::projectm_set_fps(instance, fps) };
     }
 
     fn get_aspect_correction(instance: ProjectMHandle) -> bool {
@@ -328,6 +468,16 @@ impl Projectm {
         unsafe { ffi::projectm_set_window_size(instance, width, height) };
     }
 
+    #[cfg(feature = "projectm-4-1")]
+    fn set_frame_time(instance: ProjectMHandle, seconds_since_first_frame: f64) {
+        unsafe { ffi::projectm_set_frame_time(instance, seconds_since_first_frame) };
+    }
+
+    #[cfg(feature = "projectm-4-1")]
+    fn get_last_frame_time(instance: ProjectMHandle) -> f64 {
+        unsafe { ffi::projectm_get_last_frame_time(instance) }
+    }
+
     // -----------------
     // Render OpenGL
     // -----------------
@@ -336,18 +486,25 @@ impl Projectm {
         unsafe { ffi::projectm_opengl_render_frame(instance) };
     }
 
+    #[cfg(feature = "projectm-4-1")]
+    fn render_frame_fbo(instance: ProjectMHandle, framebuffer_object_id: u32) {
+        unsafe { ffi::projectm_opengl_render_frame_fbo(instance, framebuffer_object_id) };
+    }
+
     // -----------------
     // Touch
     // -----------------
 
-    fn touch(
-        instance: ProjectMHandle,
-        x: f32,
-        y: f32,
-        pressure: i32,
-        touch_type: ProjectMTouchType,
-    ) {
-        unsafe { ffi::projectm_touch(instance, x, y, pressure, touch_type.try_into().unwrap()) };
+    fn touch(instance: ProjectMHandle, x: f32, y: f32, pressure: i32, touch_type: TouchType) {
+        unsafe {
+            ffi::projectm_touch(
+                instance,
+                x,
+                y,
+                pressure,
+                touch_type as ffi::projectm_touch_type,
+            )
+        };
     }
 
     fn touch_drag(instance: ProjectMHandle, x: f32, y: f32, pressure: i32) {
@@ -370,65 +527,17 @@ impl Projectm {
         unsafe { ffi::projectm_pcm_get_max_samples() }
     }
 
-    fn pcm_add_float(instance: ProjectMHandle, samples: Vec<f32>, channels: ProjectMChannels) {
-        assert!(
-            samples.len() <= Self::pcm_get_max_samples() as usize,
-            "Number of samples is greater than max samples"
-        );
-        let samples_per_channel = samples.len() / channels as usize;
-        unsafe {
-            ffi::projectm_pcm_add_float(
-                instance,
-                samples.as_ptr(),
-                samples_per_channel as u32,
-                channels.try_into().unwrap(),
-            )
-        }
-    }
-
-    fn pcm_add_int16(instance: ProjectMHandle, samples: Vec<i16>, channels: ProjectMChannels) {
-        assert!(
-            samples.len() <= Self::pcm_get_max_samples() as usize,
-            "Number of samples is greater than max samples"
-        );
-        let samples_per_channel = samples.len() / channels as usize;
-        unsafe {
-            ffi::projectm_pcm_add_int16(
-                instance,
-                samples.as_ptr(),
-                samples_per_channel as u32,
-                channels.try_into().unwrap(),
-            )
-        }
-    }
-
-    fn pcm_add_uint8(instance: ProjectMHandle, samples: Vec<u8>, channels: ProjectMChannels) {
-        assert!(
-            samples.len() <= Self::pcm_get_max_samples() as usize,
-            "Number of samples is greater than max samples"
-        );
-        let samples_per_channel = samples.len() / channels as usize;
-        unsafe {
-            ffi::projectm_pcm_add_uint8(
-                instance,
-                samples.as_ptr(),
-                samples_per_channel as u32,
-                channels.try_into().unwrap(),
-            )
-        }
-    }
-
     // -----------------
     // Debug
     // -----------------
 
-    fn write_debug_image_on_next_frame(instance: ProjectMHandle, output_file: Option<&String>) {
+    fn write_debug_image_on_next_frame(
+        instance: ProjectMHandle,
+        output_file: Option<&String>,
+    ) -> Result<()> {
         // Transform the Rust String into a C String - this is needed due to the
         // fact that Rust Strings are not null terminated.
-        let path = output_file.map(|p| {
-            CString::new(p.as_str())
-                .expect("Provided output file path could not be converted to a C string")
-        });
+        let path = output_file.map(|p| CString::new(p.as_str())).transpose()?;
 
         // `path` will be alive until the end of the scope, so we can safely get
         // a pointer to it.
@@ -438,369 +547,539 @@ impl Projectm {
             .unwrap_or(std::ptr::null());
 
         unsafe { ffi::projectm_write_debug_image_on_next_frame(instance, ptr) };
+
+        Ok(())
+    }
+}
+
+/// Convert a path into the NUL-terminated form expected by projectM.
+#[cfg(unix)]
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Convert a path into the NUL-terminated form expected by projectM.
+#[cfg(not(unix))]
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| Error::NonUnicodePath(path.to_path_buf()))?;
+
+    Ok(CString::new(path)?)
+}
+
+/// Convert texture search paths into the form expected by projectM.
+fn texture_search_paths_to_cstrings(texture_search_paths: &[String]) -> Result<Vec<CString>> {
+    texture_search_paths
+        .iter()
+        .map(|path| path_to_cstring(Path::new(path)))
+        .collect()
+}
+
+fn fps_to_ffi(fps: u32) -> Result<i32> {
+    i32::try_from(fps).map_err(|_| Error::OutOfRange("fps"))
+}
+
+/// Owner of the native projectM instance.
+///
+/// The instance is destroyed exactly once, when the last [`ProjectM`] (or
+/// [`Playlist`](crate::playlist::Playlist)) referencing it is dropped.
+struct Instance {
+    handle: ProjectMHandle,
+    // Dropped after the native instance has been destroyed, so projectM can
+    // never call into a freed closure.
+    callbacks: Callbacks,
+    pcm_receivers: RefCell<Vec<PcmReceiver>>,
+    // projectM has no getter for these, so keep our own copy.
+    texture_search_paths: RefCell<Vec<String>>,
+}
+
+/// State shared with the native event callbacks through `user_data`.
+struct Callbacks {
+    preset_switch_requested: PresetSwitchRequestedSlot,
+    preset_switch_failed: PresetSwitchFailedSlot,
+    events: EventSenders,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        Projectm::destroy(self.handle);
     }
 }
 
+/// Shared handle to a projectM instance.
+///
+/// Cloning a `ProjectM` yields another handle to the same native instance.
+/// The instance stays alive as long as any clone (or any playlist created
+/// from it) exists.
+///
+/// A `ProjectM` is neither `Send` nor `Sync`, as projectM must be used from
+/// the thread owning its OpenGL context. Audio can be fed from other threads
+/// through a [`PcmSender`].
 pub struct ProjectM {
-    instance: Rc<RefCell<ProjectMHandle>>,
+    instance: Rc<Instance>,
 }
 
 impl ProjectM {
-    pub fn create() -> Self {
-        let instance = Rc::new(RefCell::new(Projectm::create()));
+    pub fn create() -> Result<Self> {
+        let instance = Rc::new(Instance {
+            handle: Projectm::create()?,
+            callbacks: Callbacks {
+                preset_switch_requested: CallbackSlot::new(),
+                preset_switch_failed: CallbackSlot::new(),
+                events: EventSenders::new(),
+            },
+            pcm_receivers: RefCell::new(Vec::new()),
+            texture_search_paths: RefCell::new(Vec::new()),
+        });
 
-        ProjectM { instance }
-    }
+        let projectm = ProjectM { instance };
+        projectm.register_event_callbacks();
+
+        Ok(projectm)
+    }
+
+    /// Start configuring a new instance.
+    pub fn builder() -> ProjectMBuilder {
+        ProjectMBuilder::new()
+    }
+
+    /// Snapshot the current configuration, e.g. to persist it.
+    pub fn settings(&self) -> Result<ProjectMSettings> {
+        Ok(ProjectMSettings {
+            mesh_size: Some(self.get_mesh_size()),
+            fps: Some(self.get_fps()?),
+            beat_sensitivity: Some(self.get_beat_sensitivity()),
+            hard_cut_enabled: Some(self.get_hard_cut_enabled()),
+            hard_cut_duration: Some(self.get_hard_cut_duration()),
+            hard_cut_sensitivity: Some(self.get_hard_cut_sensitivity()),
+            soft_cut_duration: Some(self.get_soft_cut_duration()),
+            preset_duration: Some(self.get_preset_duration()),
+            preset_locked: Some(self.get_preset_locked()),
+            aspect_correction: Some(self.get_aspect_correction()),
+            easter_egg: Some(self.get_easter_egg()),
+            window_size: Some(self.get_window_size()),
+            texture_search_paths: Some(self.get_texture_search_paths()),
+        })
+    }
+
+    /// Apply every value set in `settings`.
+    ///
+    /// Settings that can be rejected are checked first, so on error the
+    /// instance is left unchanged.
+    pub fn apply_settings(&self, settings: &ProjectMSettings) -> Result<()> {
+        let texture_search_paths = match &settings.texture_search_paths {
+            Some(paths) => Some((paths, texture_search_paths_to_cstrings(paths)?)),
+            None => None,
+        };
+        let fps = settings.fps.map(fps_to_ffi).transpose()?;
 
-    pub fn destroy(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            let _ = &Projectm::destroy(*instance);
-        } else {
-            panic!("Failed to borrow instance");
+        if let Some((paths, paths_cstr)) = texture_search_paths {
+            Projectm::set_texture_search_paths(self.instance.handle, &paths_cstr);
+            *self.instance.texture_search_paths.borrow_mut() = paths.clone();
+        }
+        if let Some(fps) = fps {
+            Projectm::set_fps(self.instance.handle, fps);
         }
-    }
 
-    pub fn load_preset_file(&self, filename: &str, smooth_transition: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::load_preset_file(*instance, filename, smooth_transition);
-        } else {
-            panic!("Failed to borrow instance");
+        if let Some((mesh_x, mesh_y)) = settings.mesh_size {
+            self.set_mesh_size(mesh_x, mesh_y);
+        }
+        if let Some(sensitivity) = settings.beat_sensitivity {
+            self.set_beat_sensitivity(sensitivity);
+        }
+        if let Some(enabled) = settings.hard_cut_enabled {
+            self.set_hard_cut_enabled(enabled);
         }
+        if let Some(seconds) = settings.hard_cut_duration {
+            self.set_hard_cut_duration(seconds);
+        }
+        if let Some(sensitivity) = settings.hard_cut_sensitivity {
+            self.set_hard_cut_sensitivity(sensitivity);
+        }
+        if let Some(seconds) = settings.soft_cut_duration {
+            self.set_soft_cut_duration(seconds);
+        }
+        if let Some(seconds) = settings.preset_duration {
+            self.set_preset_duration(seconds);
+        }
+        if let Some(lock) = settings.preset_locked {
+            self.set_preset_locked(lock);
+        }
+        if let Some(enabled) = settings.aspect_correction {
+            self.set_aspect_correction(enabled);
+        }
+        if let Some(sensitivity) = settings.easter_egg {
+            self.set_easter_egg(sensitivity);
+        }
+        if let Some((width, height)) = settings.window_size {
+            self.set_window_size(width, height);
+        }
+        Ok(())
     }
 
-    pub fn load_preset_data(&self, data: &str, smooth_transition: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::load_preset_data(*instance, data, smooth_transition);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Release this handle.
+    ///
+    /// The native instance is only destroyed once every other clone and
+    /// every playlist created from it has been dropped as well.
+    pub fn destroy(self) {
+        drop(self);
+    }
+
+    /// Load a preset from a file.
+    ///
+    /// On Unix the path is passed to projectM as raw bytes, so non-UTF-8
+    /// file names are supported.
+    pub fn load_preset_file<P: AsRef<Path>>(
+        &self,
+        filename: P,
+        smooth_transition: bool,
+    ) -> Result<()> {
+        let filename = path_to_cstring(filename.as_ref())?;
+        Projectm::load_preset_file(self.instance.handle, &filename, smooth_transition);
+        Ok(())
+    }
+
+    /// Load a preset from a file given as a NUL-terminated string.
+    pub fn load_preset_file_cstr(&self, filename: &CStr, smooth_transition: bool) {
+        Projectm::load_preset_file(self.instance.handle, filename, smooth_transition);
+    }
+
+    /// Load a preset from its `.milk` source.
+    pub fn load_preset_data(&self, data: &str, smooth_transition: bool) -> Result<()> {
+        let data = CString::new(data)?;
+        Projectm::load_preset_data(self.instance.handle, &data, smooth_transition);
+        Ok(())
+    }
+
+    /// Load a preset from its `.milk` source given as a NUL-terminated string.
+    pub fn load_preset_data_cstr(&self, data: &CStr, smooth_transition: bool) {
+        Projectm::load_preset_data(self.instance.handle, data, smooth_transition);
     }
 
     pub fn reset_textures(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::reset_textures(*instance);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::reset_textures(self.instance.handle);
     }
 
     pub fn get_version_components() -> (i32, i32, i32) {
         Projectm::get_version_components()
     }
 
-    pub fn get_version_string() -> String {
+    /// The version of the loaded libprojectM, see [`Version::runtime`].
+    pub fn get_version() -> Result<Version> {
+        Version::runtime()
+    }
+
+    pub fn get_version_string() -> Result<String> {
         Projectm::get_version_string()
     }
 
-    pub fn get_vcs_version_string() -> String {
+    pub fn get_vcs_version_string() -> Result<String> {
         Projectm::get_vcs_version_string()
     }
 
+    /// Set the callback invoked when projectM wants to switch presets.
+    ///
+    /// Any previously set callback is dropped. A panic inside the callback
+    /// is caught before it reaches projectM. Note that creating a
+    /// [`Playlist`](crate::playlist::Playlist) installs the playlist's own
+    /// handler in place of this callback until the playlist is dropped.
     pub fn set_preset_switch_requested_event_callback<F: FnMut(bool) + 'static>(
         &self,
         callback: F,
     ) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_switch_requested_event_callback(*instance, callback);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        self.instance
+            .callbacks
+            .preset_switch_requested
+            .set(Box::new(callback));
+    }
+
+    /// Remove the preset switch requested callback.
+    pub fn clear_preset_switch_requested_event_callback(&self) {
+        self.instance.callbacks.preset_switch_requested.clear();
     }
 
+    /// Set the callback invoked when loading a preset fails.
+    ///
+    /// Any previously set callback is dropped. A panic inside the callback
+    /// is caught before it reaches projectM. While a
+    /// [`Playlist`](crate::playlist::Playlist) created from this instance is
+    /// alive, it handles failed preset switches itself and this callback is
+    /// not invoked; it is reinstated when the playlist is dropped.
     pub fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
         &self,
         callback: F,
     ) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_switch_failed_event_callback(*instance, callback);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        self.instance
+            .callbacks
+            .preset_switch_failed
+            .set(Box::new(callback));
     }
 
-    pub fn set_texture_search_paths(&self, texture_search_paths: &[String], count: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_texture_search_paths(*instance, texture_search_paths, count);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Remove the preset switch failed callback.
+    pub fn clear_preset_switch_failed_event_callback(&self) {
+        self.instance.callbacks.preset_switch_failed.clear();
+    }
+
+    /// Subscribe to events from this instance and any playlist created from
+    /// it.
+    ///
+    /// Each call returns a new receiver; events are delivered to every
+    /// receiver that is still alive. While a
+    /// [`Playlist`](crate::playlist::Playlist) is alive, it takes over
+    /// projectM's preset switch events and reports them as playlist events
+    /// instead of [`Event::PresetSwitchRequested`] and
+    /// [`Event::PresetSwitchFailed`].
+    pub fn events(&self) -> Receiver<Event> {
+        self.instance.callbacks.events.subscribe()
+    }
+
+    /// Subscribe to events as an asynchronous stream.
+    #[cfg(feature = "stream")]
+    pub fn event_stream(&self) -> EventStream {
+        self.instance.callbacks.events.subscribe_stream()
+    }
+
+    pub fn set_texture_search_paths(&self, texture_search_paths: &[String]) -> Result<()> {
+        let texture_search_paths_cstr = texture_search_paths_to_cstrings(texture_search_paths)?;
+        Projectm::set_texture_search_paths(self.instance.handle, &texture_search_paths_cstr);
+        *self.instance.texture_search_paths.borrow_mut() = texture_search_paths.to_vec();
+        Ok(())
+    }
+
+    /// The texture search paths last set on this instance.
+    pub fn get_texture_search_paths(&self) -> Vec<String> {
+        self.instance.texture_search_paths.borrow().clone()
     }
 
     pub fn get_beat_sensitivity(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_beat_sensitivity(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_beat_sensitivity(self.instance.handle)
     }
 
     pub fn set_beat_sensitivity(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_beat_sensitivity(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_beat_sensitivity(self.instance.handle, sensitivity);
     }
 
     pub fn get_hard_cut_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_hard_cut_duration(self.instance.handle)
     }
 
     pub fn set_hard_cut_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_hard_cut_duration(self.instance.handle, seconds);
     }
 
     pub fn get_hard_cut_enabled(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_enabled(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_hard_cut_enabled(self.instance.handle)
     }
 
     pub fn set_hard_cut_enabled(&self, enabled: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_enabled(*instance, enabled);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_hard_cut_enabled(self.instance.handle, enabled);
     }
 
     pub fn get_hard_cut_sensitivity(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_hard_cut_sensitivity(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_hard_cut_sensitivity(self.instance.handle)
     }
 
     pub fn set_hard_cut_sensitivity(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_hard_cut_sensitivity(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_hard_cut_sensitivity(self.instance.handle, sensitivity);
     }
 
     pub fn get_soft_cut_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_soft_cut_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_soft_cut_duration(self.instance.handle)
     }
 
     pub fn set_soft_cut_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_soft_cut_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_soft_cut_duration(self.instance.handle, seconds);
     }
 
     pub fn get_preset_duration(&self) -> f64 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_preset_duration(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_preset_duration(self.instance.handle)
     }
 
     pub fn set_preset_duration(&self, seconds: f64) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_duration(*instance, seconds);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_preset_duration(self.instance.handle, seconds);
     }
 
     pub fn get_mesh_size(&self) -> (usize, usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_mesh_size(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_mesh_size(self.instance.handle)
     }
 
     pub fn set_mesh_size(&self, mesh_x: usize, mesh_y: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_mesh_size(*instance, mesh_x, mesh_y);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_mesh_size(self.instance.handle, mesh_x, mesh_y);
     }
 
-    pub fn get_fps(&self) -> u32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_fps(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    pub fn get_fps(&self) -> Result<u32> {
+        Projectm::get_fps(self.instance.handle)
     }
 
-    pub fn set_fps(&self, fps: u32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_fps(*instance, fps);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    pub fn set_fps(&self, fps: u32) -> Result<()> {
+        Projectm::set_fps(self.instance.handle, fps_to_ffi(fps)?);
+        Ok(())
     }
 
     pub fn get_aspect_correction(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_aspect_correction(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_aspect_correction(self.instance.handle)
     }
 
     pub fn set_aspect_correction(&self, enabled: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_aspect_correction(*instance, enabled);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_aspect_correction(self.instance.handle, enabled);
     }
 
     pub fn get_easter_egg(&self) -> f32 {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_easter_egg(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_easter_egg(self.instance.handle)
     }
 
     pub fn set_easter_egg(&self, sensitivity: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_easter_egg(*instance, sensitivity);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_easter_egg(self.instance.handle, sensitivity);
     }
 
     pub fn get_preset_locked(&self) -> bool {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_preset_locked(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_preset_locked(self.instance.handle)
     }
 
     pub fn set_preset_locked(&self, lock: bool) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_preset_locked(*instance, lock);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_preset_locked(self.instance.handle, lock);
     }
 
     pub fn get_window_size(&self) -> (usize, usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::get_window_size(*instance)
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::get_window_size(self.instance.handle)
     }
 
     pub fn set_window_size(&self, width: usize, height: usize) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::set_window_size(*instance, width, height);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::set_window_size(self.instance.handle, width, height);
+    }
+
+    /// Drive animation from an external clock instead of the system time,
+    /// e.g. for rendering to video. Requires libprojectM 4.1.
+    #[cfg(feature = "projectm-4-1")]
+    pub fn set_frame_time(&self, seconds_since_first_frame: f64) {
+        Projectm::set_frame_time(self.instance.handle, seconds_since_first_frame);
+    }
+
+    /// The time of the last rendered frame, in seconds since the first one.
+    /// Requires libprojectM 4.1.
+    #[cfg(feature = "projectm-4-1")]
+    pub fn get_last_frame_time(&self) -> f64 {
+        Projectm::get_last_frame_time(self.instance.handle)
     }
 
+    /// Render a frame, after feeding projectM everything queued by this
+    /// instance's [`PcmSender`]s.
     pub fn render_frame(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::render_frame(*instance);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        self.drain_pcm();
+        Projectm::render_frame(self.instance.handle);
     }
 
-    pub fn touch(&self, x: f32, y: f32, pressure: i32, touch_type: ProjectMTouchType) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch(*instance, x, y, pressure, touch_type);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Render a frame into the given framebuffer object instead of the
+    /// default framebuffer. Requires libprojectM 4.1.
+    #[cfg(feature = "projectm-4-1")]
+    pub fn render_frame_fbo(&self, framebuffer_object_id: u32) {
+        self.drain_pcm();
+        Projectm::render_frame_fbo(self.instance.handle, framebuffer_object_id);
+    }
+
+    pub fn touch(&self, x: f32, y: f32, pressure: i32, touch_type: TouchType) {
+        Projectm::touch(self.instance.handle, x, y, pressure, touch_type);
     }
 
     pub fn touch_drag(&self, x: f32, y: f32, pressure: i32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_drag(*instance, x, y, pressure);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::touch_drag(self.instance.handle, x, y, pressure);
     }
 
     pub fn touch_destroy(&self, x: f32, y: f32) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_destroy(*instance, x, y);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::touch_destroy(self.instance.handle, x, y);
     }
 
     pub fn touch_destroy_all(&self) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::touch_destroy_all(*instance);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+        Projectm::touch_destroy_all(self.instance.handle);
     }
 
     pub fn pcm_get_max_samples() -> u32 {
         Projectm::pcm_get_max_samples()
     }
 
-    pub fn pcm_add_float(&self, samples: Vec<f32>, channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_float(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
+    /// Add interleaved PCM samples.
+    ///
+    /// Buffers longer than [`pcm_get_max_samples`](Self::pcm_get_max_samples)
+    /// frames are passed to projectM in several chunks. A trailing partial
+    /// frame (e.g. an odd sample count in stereo) is ignored. Nothing is
+    /// allocated, so this is safe to call from an audio path.
+    pub fn pcm_add<S: Sample>(&self, samples: &[S], channels: Channels) {
+        let frame_len = channels.count();
+        let samples = &samples[..samples.len() - samples.len() % frame_len];
+        // At least one frame per chunk, even if projectM reports no limit.
+        let chunk_len = (Projectm::pcm_get_max_samples() as usize).max(1) * frame_len;
+
+        for chunk in samples.chunks(chunk_len) {
+            unsafe { S::add_chunk(self.instance.handle, chunk, channels) };
         }
     }
 
-    pub fn pcm_add_int16(&self, samples: Vec<i16>, channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_int16(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Create a sender through which another thread can queue PCM data.
+    ///
+    /// The sender writes into a lock-free ring buffer holding `capacity`
+    /// samples, rounded up to whole frames. The queued audio is passed to
+    /// projectM by [`drain_pcm`](Self::drain_pcm), which
+    /// [`render_frame`](Self::render_frame) calls before rendering.
+    pub fn pcm_sender(&self, channels: Channels, capacity: usize) -> PcmSender {
+        let (sender, receiver) = PcmSender::new(channels, capacity);
+        self.instance.pcm_receivers.borrow_mut().push(receiver);
+        sender
     }
 
-    pub fn pcm_add_uint8(&self, samples: Vec<u8>, channels: ProjectMChannels) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::pcm_add_uint8(*instance, samples, channels);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    /// Pass all PCM data queued by this instance's senders to projectM.
+    ///
+    /// Receivers whose sender has been dropped are forgotten once they are
+    /// empty.
+    pub fn drain_pcm(&self) {
+        self.instance
+            .pcm_receivers
+            .borrow_mut()
+            .retain_mut(|receiver| {
+                receiver.drain(|samples, channels| self.pcm_add(samples, channels))
+            });
     }
 
-    pub fn write_debug_image_on_next_frame(&self, output_file: Option<&String>) {
-        if let Ok(instance) = self.instance.try_borrow() {
-            Projectm::write_debug_image_on_next_frame(*instance, output_file);
-        } else {
-            panic!("Failed to borrow instance");
-        }
+    pub fn pcm_add_float(&self, samples: &[f32], channels: Channels) {
+        self.pcm_add(samples, channels);
     }
 
-    pub fn get_instance(&self) -> Rc<RefCell<ProjectMHandle>> {
-        self.instance.clone()
+    pub fn pcm_add_int16(&self, samples: &[i16], channels: Channels) {
+        self.pcm_add(samples, channels);
+    }
+
+    pub fn pcm_add_uint8(&self, samples: &[u8], channels: Channels) {
+        self.pcm_add(samples, channels);
     }
-}
 
-unsafe impl Send for ProjectM {}
-unsafe impl Sync for ProjectM {}
+    pub fn write_debug_image_on_next_frame(&self, output_file: Option<&String>) -> Result<()> {
+        Projectm::write_debug_image_on_next_frame(self.instance.handle, output_file)
+    }
+
+    /// Get the raw native handle.
+    ///
+    /// The handle is only valid while this `ProjectM` (or a clone of it) is
+    /// alive; it must not be passed to `projectm_destroy`.
+    pub fn get_instance(&self) -> ProjectMHandle {
+        self.instance.handle
+    }
+
+    /// Point projectM's native preset switch callbacks at this instance.
+    ///
+    /// A playlist replaces these callbacks while it exists, so they are
+    /// registered again whenever a playlist is destroyed.
+    pub(crate) fn register_event_callbacks(&self) {
+        let instance = &self.instance;
+        Projectm::set_preset_switch_requested_event_callback(instance.handle, &instance.callbacks);
+        Projectm::set_preset_switch_failed_event_callback(instance.handle, &instance.callbacks);
+    }
+
+    pub(crate) fn event_senders(&self) -> &EventSenders {
+        &self.instance.callbacks.events
+    }
+}
 
 // allow cloning the handle
 impl Clone for ProjectM {
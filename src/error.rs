@@ -0,0 +1,133 @@
+use std::ffi::NulError;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::Utf8Error;
+
+use crate::preset::Span;
+use crate::version::Version;
+
+/// Errors returned by the safe projectM wrapper.
+///
+/// Some variants only exist with certain features enabled, so the enum is
+/// `#[non_exhaustive]` to keep those features additive.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A string or path passed to projectM contained an interior NUL byte.
+    InteriorNul(NulError),
+    /// A string returned by projectM was not valid UTF-8.
+    InvalidUtf8(Utf8Error),
+    /// A path could not be represented in the encoding projectM expects.
+    NonUnicodePath(PathBuf),
+    /// `projectm_create` returned a null instance.
+    NullInstance,
+    /// `projectm_playlist_create` returned a null playlist.
+    NullPlaylist,
+    /// A channel count other than mono or stereo was passed.
+    InvalidChannels(u32),
+    /// A value that is not a valid projectM touch type was passed.
+    InvalidTouchType(u32),
+    /// A name could not be parsed into the expected kind of value.
+    UnknownName { kind: &'static str, name: String },
+    /// A value did not fit the range accepted or returned by projectM.
+    OutOfRange(&'static str),
+    /// A playlist index was past the end of the playlist.
+    IndexOutOfRange { index: u32, len: u32 },
+    /// The operation requires a non-empty playlist.
+    EmptyPlaylist,
+    /// A version string could not be parsed.
+    InvalidVersion(String),
+    /// The libprojectM loaded at runtime does not match the bindings.
+    IncompatibleVersion { runtime: Version, bindings: Version },
+    /// A preset file could not be read.
+    Io(std::io::Error),
+    /// A preset could not be parsed.
+    PresetSyntax { line: usize, message: String },
+    /// Preset equation code could not be parsed.
+    ExpressionSyntax { span: Span, message: String },
+    /// A playlist filter regex failed to compile.
+    #[cfg(feature = "regex")]
+    InvalidRegex(regex::Error),
+}
+
+/// Result type used throughout the safe wrapper.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InteriorNul(err) => write!(f, "string contains an interior NUL byte: {err}"),
+            Error::InvalidUtf8(err) => write!(f, "projectM returned invalid UTF-8: {err}"),
+            Error::NonUnicodePath(path) => {
+                write!(f, "path is not valid unicode: {}", path.display())
+            }
+            Error::NullInstance => write!(f, "projectM failed to create an instance"),
+            Error::NullPlaylist => write!(f, "projectM failed to create a playlist"),
+            Error::InvalidChannels(channels) => {
+                write!(
+                    f,
+                    "invalid channel count {channels}, expected mono or stereo"
+                )
+            }
+            Error::InvalidTouchType(value) => write!(f, "invalid touch type {value}"),
+            Error::UnknownName { kind, name } => write!(f, "unknown {kind} \"{name}\""),
+            Error::OutOfRange(what) => write!(f, "{what} is out of range"),
+            Error::IndexOutOfRange { index, len } => {
+                write!(f, "playlist index {index} out of range for length {len}")
+            }
+            Error::EmptyPlaylist => write!(f, "playlist is empty"),
+            Error::InvalidVersion(version) => write!(f, "invalid version \"{version}\""),
+            Error::IncompatibleVersion { runtime, bindings } => write!(
+                f,
+                "libprojectM {runtime} is not compatible with bindings for {bindings}"
+            ),
+            Error::Io(err) => write!(f, "failed to read preset: {err}"),
+            Error::PresetSyntax { line, message } => {
+                write!(f, "preset syntax error on line {line}: {message}")
+            }
+            Error::ExpressionSyntax { span, message } => {
+                write!(f, "expression syntax error at {span}: {message}")
+            }
+            #[cfg(feature = "regex")]
+            Error::InvalidRegex(err) => write!(f, "invalid filter regex: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InteriorNul(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            Error::Io(err) => Some(err),
+            #[cfg(feature = "regex")]
+            Error::InvalidRegex(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<NulError> for Error {
+    fn from(err: NulError) -> Self {
+        Error::InteriorNul(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Self {
+        Error::InvalidUtf8(err)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Self {
+        Error::InvalidRegex(err)
+    }
+}
@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+use std::sync::mpsc;
+
+/// Events emitted by projectM and its playlist.
+///
+/// Subscribe with [`ProjectM::events`](crate::core::ProjectM::events) and poll
+/// the receiver, e.g. once per frame with `try_iter()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// projectM wants to switch to another preset.
+    PresetSwitchRequested { hard_cut: bool },
+    /// projectM failed to load a preset.
+    PresetSwitchFailed { filename: String, message: String },
+    /// The playlist switched to the preset at `index`.
+    PlaylistPresetSwitched { hard_cut: bool, index: u32 },
+    /// The playlist failed to load a preset.
+    PlaylistPresetSwitchFailed { filename: String, message: String },
+}
+
+/// Asynchronous stream of [`Event`]s.
+#[cfg(feature = "stream")]
+pub type EventStream = futures_channel::mpsc::UnboundedReceiver<Event>;
+
+/// Subscribers of a projectM instance's events.
+pub(crate) struct EventSenders {
+    senders: RefCell<Vec<mpsc::Sender<Event>>>,
+    #[cfg(feature = "stream")]
+    streams: RefCell<Vec<futures_channel::mpsc::UnboundedSender<Event>>>,
+}
+
+impl EventSenders {
+    pub(crate) fn new() -> Self {
+        EventSenders {
+            senders: RefCell::new(Vec::new()),
+            #[cfg(feature = "stream")]
+            streams: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.borrow_mut().push(sender);
+        receiver
+    }
+
+    #[cfg(feature = "stream")]
+    pub(crate) fn subscribe_stream(&self) -> EventStream {
+        let (sender, receiver) = futures_channel::mpsc::unbounded();
+        self.streams.borrow_mut().push(sender);
+        receiver
+    }
+
+    /// Send an event to every subscriber, forgetting those that hung up.
+    pub(crate) fn send(&self, event: Event) {
+        self.senders
+            .borrow_mut()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+
+        #[cfg(feature = "stream")]
+        self.streams
+            .borrow_mut()
+            .retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+}
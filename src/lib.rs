@@ -1,4 +1,24 @@
+#[cfg(feature = "native")]
+mod callback;
+#[cfg(feature = "native")]
 pub mod core;
+pub mod error;
+#[cfg(feature = "native")]
+pub mod event;
+#[cfg(feature = "native")]
+pub mod pcm;
+pub mod preset;
+#[cfg(feature = "native")]
+pub mod settings;
+pub mod version;
 
 // #[cfg(playlist)]
+#[cfg(feature = "native")]
 pub mod playlist;
+
+pub use crate::error::{Error, Result};
+#[cfg(feature = "native")]
+pub use crate::event::Event;
+#[cfg(feature = "native")]
+pub use crate::version::check_runtime_version;
+pub use crate::version::Version;
@@ -0,0 +1,200 @@
+extern crate projectm_sys as ffi;
+
+use crate::core::{Channels, ProjectMHandle};
+
+/// Number of converted samples buffered on the stack at a time. Must be a
+/// multiple of every supported channel count.
+const CONVERSION_BUFFER_LEN: usize = 1024;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+    impl Sealed for i16 {}
+    impl Sealed for u8 {}
+    impl Sealed for i32 {}
+    impl Sealed for f64 {}
+}
+
+/// A PCM sample type accepted by [`ProjectM::pcm_add`](crate::core::ProjectM::pcm_add).
+///
+/// `f32`, `i16` and `u8` are passed to projectM as they are; `i32` and `f64`
+/// are converted to `f32` on the fly through a small stack buffer.
+pub trait Sample: Copy + sealed::Sealed {
+    /// The sample as a float in `-1.0..=1.0`.
+    fn to_f32(self) -> f32;
+
+    /// Pass interleaved samples holding at most `pcm_get_max_samples()`
+    /// whole frames to projectM.
+    ///
+    /// # Safety
+    ///
+    /// `instance` must be a valid projectM instance.
+    #[doc(hidden)]
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels);
+}
+
+fn frames(samples: usize, channels: Channels) -> u32 {
+    (samples / channels.count()) as u32
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels) {
+        unsafe {
+            ffi::projectm_pcm_add_float(
+                instance,
+                samples.as_ptr(),
+                frames(samples.len(), channels),
+                channels as ffi::projectm_channels,
+            )
+        };
+    }
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels) {
+        unsafe {
+            ffi::projectm_pcm_add_int16(
+                instance,
+                samples.as_ptr(),
+                frames(samples.len(), channels),
+                channels as ffi::projectm_channels,
+            )
+        };
+    }
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        (self as f32 - 128.0) / 128.0
+    }
+
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels) {
+        unsafe {
+            ffi::projectm_pcm_add_uint8(
+                instance,
+                samples.as_ptr(),
+                frames(samples.len(), channels),
+                channels as ffi::projectm_channels,
+            )
+        };
+    }
+}
+
+/// Convert `samples` to `f32` in stack-sized pieces and pass them on.
+unsafe fn add_converted<S: Sample>(instance: ProjectMHandle, samples: &[S], channels: Channels) {
+    let mut buffer = [0.0f32; CONVERSION_BUFFER_LEN];
+    for chunk in samples.chunks(CONVERSION_BUFFER_LEN) {
+        let converted = &mut buffer[..chunk.len()];
+        for (target, sample) in converted.iter_mut().zip(chunk) {
+            *target = sample.to_f32();
+        }
+        unsafe { f32::add_chunk(instance, converted, channels) };
+    }
+}
+
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        (self as f64 / 2147483648.0) as f32
+    }
+
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels) {
+        unsafe { add_converted(instance, samples, channels) };
+    }
+}
+
+impl Sample for f64 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    unsafe fn add_chunk(instance: ProjectMHandle, samples: &[Self], channels: Channels) {
+        unsafe { add_converted(instance, samples, channels) };
+    }
+}
+
+/// Sending half of a PCM queue, created by
+/// [`ProjectM::pcm_sender`](crate::core::ProjectM::pcm_sender).
+///
+/// The sender is `Send`, so it can be moved to an audio thread while the
+/// `ProjectM` instance stays on the render thread. Writing never blocks or
+/// allocates; samples that don't fit into the queue are dropped.
+pub struct PcmSender {
+    producer: rtrb::Producer<f32>,
+    channels: Channels,
+}
+
+/// Receiving half of a PCM queue, owned by the projectM instance.
+pub(crate) struct PcmReceiver {
+    consumer: rtrb::Consumer<f32>,
+    channels: Channels,
+}
+
+impl PcmSender {
+    pub(crate) fn new(channels: Channels, capacity: usize) -> (PcmSender, PcmReceiver) {
+        // Keep the capacity a whole number of frames so a frame never wraps
+        // around the end of the buffer.
+        let frame_len = channels.count();
+        let capacity = (capacity.max(1) + frame_len - 1) / frame_len * frame_len;
+        let (producer, consumer) = rtrb::RingBuffer::new(capacity);
+
+        (
+            PcmSender { producer, channels },
+            PcmReceiver { consumer, channels },
+        )
+    }
+
+    /// The channel layout of the queued samples.
+    pub fn channels(&self) -> Channels {
+        self.channels
+    }
+
+    /// Number of samples that can currently be queued.
+    pub fn free(&self) -> usize {
+        self.producer.slots()
+    }
+
+    /// Whether the projectM instance reading this queue has been dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.producer.is_abandoned()
+    }
+
+    /// Queue interleaved samples.
+    ///
+    /// Only whole frames are queued, and only as many as currently fit.
+    /// Returns the number of samples taken from `samples`.
+    pub fn send<S: Sample>(&mut self, samples: &[S]) -> usize {
+        let frame_len = self.channels.count();
+        let free = self.producer.slots() - self.producer.slots() % frame_len;
+        let len = (samples.len() - samples.len() % frame_len).min(free);
+
+        match self.producer.write_chunk_uninit(len) {
+            Ok(chunk) => chunk.fill_from_iter(samples[..len].iter().map(|s| s.to_f32())),
+            Err(_) => 0,
+        }
+    }
+}
+
+impl PcmReceiver {
+    /// Hand everything queued so far to `add`. Returns `false` once the
+    /// sender is gone and nothing is left to read.
+    pub(crate) fn drain(&mut self, mut add: impl FnMut(&[f32], Channels)) -> bool {
+        let available = self.consumer.slots();
+        if let Ok(chunk) = self.consumer.read_chunk(available) {
+            let (first, second) = chunk.as_slices();
+            add(first, self.channels);
+            add(second, self.channels);
+            chunk.commit_all();
+        }
+
+        !self.consumer.is_abandoned() || !self.consumer.is_empty()
+    }
+}
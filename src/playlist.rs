@@ -1,32 +1,640 @@
 extern crate libc;
 extern crate projectm_sys as ffi;
 
-use rand::Rng;
-use std::ffi::CString;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::ffi::{CStr, CString};
+use std::path::Path;
+use std::sync::mpsc;
 
-use crate::core::ProjectM;
+use crate::callback::CallbackSlot;
+use crate::core::{path_to_cstring, ProjectM};
+use crate::error::{Error, Result};
+use crate::event::Event;
 
+/// Which part of a preset's path [`Playlist::sort`] compares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortPredicate {
+    /// Compare the full path.
+    FullPath,
+    /// Compare only the file name.
+    FilenameOnly,
+}
+
+impl SortPredicate {
+    fn to_ffi(self) -> ffi::projectm_playlist_sort_predicate {
+        match self {
+            SortPredicate::FullPath => {
+                ffi::projectm_playlist_sort_predicate_SORT_PREDICATE_FULL_PATH
+            }
+            SortPredicate::FilenameOnly => {
+                ffi::projectm_playlist_sort_predicate_SORT_PREDICATE_FILENAME_ONLY
+            }
+        }
+    }
+}
+
+/// Direction of [`Playlist::sort`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    fn to_ffi(self) -> ffi::projectm_playlist_sort_order {
+        match self {
+            SortOrder::Ascending => ffi::projectm_playlist_sort_order_SORT_ORDER_ASCENDING,
+            SortOrder::Descending => ffi::projectm_playlist_sort_order_SORT_ORDER_DESCENDING,
+        }
+    }
+}
+
+/// A single rule of a [`PlaylistFilter`].
+#[derive(Debug, Clone)]
+enum FilterRule {
+    Glob {
+        pattern: String,
+        include: bool,
+    },
+    #[cfg(feature = "regex")]
+    Regex {
+        regex: regex::Regex,
+        include: bool,
+    },
+}
+
+impl FilterRule {
+    /// The rule in libprojectM's filter list syntax, if it is a glob rule.
+    fn native_entry(&self) -> Option<String> {
+        match self {
+            FilterRule::Glob { pattern, include } => {
+                let prefix = if *include { '+' } else { '-' };
+                Some(format!("{prefix}{pattern}"))
+            }
+            #[cfg(feature = "regex")]
+            FilterRule::Regex { .. } => None,
+        }
+    }
+}
+
+/// Builder for include/exclude rules applied to a [`Playlist`].
+///
+/// Glob rules are handed to libprojectM's filter list, matched against the
+/// full preset path, and also apply to presets added later on. Regex rules
+/// (behind the `regex` feature) are evaluated on the Rust side, so the
+/// filter has to be [applied](Self::apply) again after adding presets.
+///
+/// Within each kind the first matching rule wins; presets matching no rule
+/// are kept. Glob rules are evaluated before regex rules.
+///
+/// ```no_run
+/// # use projectm::core::ProjectM;
+/// # use projectm::playlist::{Playlist, PlaylistFilter};
+/// # fn main() -> projectm::Result<()> {
+/// let projectm = ProjectM::create()?;
+/// let mut playlist = Playlist::create(&projectm)?;
+/// playlist.add_path("presets", true)?;
+///
+/// let filter = PlaylistFilter::new().exclude("**/*-wave*.milk");
+/// let report = filter.apply(&mut playlist)?;
+/// println!("removed {} presets", report.total());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistFilter {
+    rules: Vec<FilterRule>,
+}
+
+impl PlaylistFilter {
+    pub fn new() -> Self {
+        PlaylistFilter::default()
+    }
+
+    /// Keep presets matching the glob `pattern`.
+    pub fn include<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.rules.push(FilterRule::Glob {
+            pattern: pattern.into(),
+            include: true,
+        });
+        self
+    }
+
+    /// Remove presets matching the glob `pattern`.
+    pub fn exclude<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.rules.push(FilterRule::Glob {
+            pattern: pattern.into(),
+            include: false,
+        });
+        self
+    }
+
+    /// Keep presets whose full path matches the regular expression.
+    #[cfg(feature = "regex")]
+    pub fn include_regex(mut self, pattern: &str) -> Result<Self> {
+        self.rules.push(FilterRule::Regex {
+            regex: regex::Regex::new(pattern)?,
+            include: true,
+        });
+        Ok(self)
+    }
+
+    /// Remove presets whose full path matches the regular expression.
+    #[cfg(feature = "regex")]
+    pub fn exclude_regex(mut self, pattern: &str) -> Result<Self> {
+        self.rules.push(FilterRule::Regex {
+            regex: regex::Regex::new(pattern)?,
+            include: false,
+        });
+        Ok(self)
+    }
+
+    /// The rules in libprojectM's filter list syntax, with the index of the
+    /// rule each entry came from.
+    fn native_filter(&self) -> Vec<(usize, String)> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter_map(|(rule, filter_rule)| Some((rule, filter_rule.native_entry()?)))
+            .collect()
+    }
+
+    /// Apply the filter to `playlist`, removing excluded presets.
+    ///
+    /// Replaces the playlist's native filter list with this filter's glob
+    /// rules.
+    pub fn apply(&self, playlist: &mut Playlist) -> Result<FilterReport> {
+        let mut removed = vec![0; self.rules.len()];
+
+        // Growing the native filter one rule at a time attributes each
+        // removal to the first rule that matched it.
+        let native = self.native_filter();
+        let entries: Vec<_> = native.iter().map(|(_, entry)| entry.as_str()).collect();
+        for (count, (rule, _)) in native.iter().enumerate() {
+            playlist.set_filter(&entries[..=count])?;
+            removed[*rule] = playlist.apply_filter();
+        }
+        playlist.set_filter(&entries)?;
+
+        #[cfg(feature = "regex")]
+        self.apply_regex_rules(playlist, &mut removed)?;
+
+        Ok(FilterReport { removed })
+    }
+
+    #[cfg(feature = "regex")]
+    fn apply_regex_rules(&self, playlist: &mut Playlist, removed: &mut [usize]) -> Result<()> {
+        if !self
+            .rules
+            .iter()
+            .any(|rule| matches!(rule, FilterRule::Regex { .. }))
+        {
+            return Ok(());
+        }
+
+        let items = playlist.items()?;
+        for (index, item) in items.iter().enumerate().rev() {
+            let first_match = self
+                .rules
+                .iter()
+                .enumerate()
+                .find_map(|(rule, filter_rule)| match filter_rule {
+                    FilterRule::Regex { regex, include } if regex.is_match(item) => {
+                        Some((rule, *include))
+                    }
+                    _ => None,
+                });
+
+            if let Some((rule, false)) = first_match {
+                playlist.remove_preset(index as u32)?;
+                removed[rule] += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of presets removed by each rule of a [`PlaylistFilter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterReport {
+    removed: Vec<usize>,
+}
+
+impl FilterReport {
+    /// Presets removed per rule, in the order the rules were added.
+    pub fn removed(&self) -> &[usize] {
+        &self.removed
+    }
+
+    /// Presets removed by the rule at `rule`.
+    pub fn removed_by(&self, rule: usize) -> usize {
+        self.removed.get(rule).copied().unwrap_or(0)
+    }
+
+    /// Total number of presets removed.
+    pub fn total(&self) -> usize {
+        self.removed.iter().sum()
+    }
+}
+
+/// Maximum number of positions kept in a playlist's history.
+const HISTORY_LIMIT: usize = 1000;
+
+/// Positions visited through a [`Playlist`], with browser-like back and
+/// forward navigation.
+struct History {
+    entries: Vec<u32>,
+    // Index into `entries` of the current position.
+    cursor: usize,
+}
+
+impl History {
+    fn new() -> Self {
+        History {
+            entries: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    fn current(&self) -> Option<u32> {
+        self.entries.get(self.cursor).copied()
+    }
+
+    /// Record a jump, discarding any forward entries.
+    fn push(&mut self, index: u32) {
+        if self.current() == Some(index) {
+            return;
+        }
+
+        self.entries.truncate(self.cursor + 1);
+        self.entries.push(index);
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.remove(0);
+        }
+        self.cursor = self.entries.len() - 1;
+    }
+
+    fn back(&mut self) -> Option<u32> {
+        if self.cursor == 0 || self.entries.is_empty() {
+            return None;
+        }
+        self.cursor -= 1;
+        self.current()
+    }
+
+    fn forward(&mut self) -> Option<u32> {
+        if self.cursor + 1 >= self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.current()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.cursor = 0;
+    }
+
+    /// Shift entries after `count` presets were inserted at `index`.
+    fn inserted(&mut self, index: u32, count: u32) {
+        for entry in self.entries.iter_mut().filter(|entry| **entry >= index) {
+            *entry += count;
+        }
+    }
+
+    /// Drop and shift entries after `count` presets were removed at `index`.
+    fn removed(&mut self, index: u32, count: u32) {
+        let end = index.saturating_add(count);
+        let mut cursor = self.cursor;
+        let mut kept = 0;
+        for position in 0..self.entries.len() {
+            let entry = self.entries[position];
+            if entry >= index && entry < end {
+                if position <= self.cursor {
+                    cursor = cursor.saturating_sub(1);
+                }
+                continue;
+            }
+            self.entries[kept] = if entry >= end { entry - count } else { entry };
+            kept += 1;
+        }
+        self.entries.truncate(kept);
+        self.cursor = cursor.min(kept.saturating_sub(1));
+    }
+}
+
+/// The order shuffled navigation follows in deterministic mode.
+///
+/// Every index is played once before any repeats; a new permutation is
+/// drawn when the current one is used up or the playlist size changed.
+struct ShuffleOrder {
+    order: Vec<u32>,
+    next: usize,
+}
+
+impl ShuffleOrder {
+    fn new() -> Self {
+        ShuffleOrder {
+            order: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// The next index to play in a non-empty playlist of `len` presets.
+    fn next(&mut self, len: u32, current: u32, rng: &mut dyn RngCore) -> u32 {
+        if self.next >= self.order.len() || self.order.len() != len as usize {
+            self.order = (0..len).collect();
+            self.order.shuffle(rng);
+            // Don't repeat the current preset where two permutations meet.
+            if len > 1 && self.order[0] == current {
+                self.order.swap(0, len as usize - 1);
+            }
+            self.next = 0;
+        }
+
+        let index = self.order[self.next];
+        self.next += 1;
+        index
+    }
+
+    fn reset(&mut self) {
+        self.order.clear();
+        self.next = 0;
+    }
+}
+
+type PresetSwitchedSlot = CallbackSlot<dyn FnMut(bool, u32)>;
+type PresetSwitchFailedSlot = CallbackSlot<dyn FnMut(String, String)>;
+
+/// State shared with the native playlist callbacks through `user_data`.
+struct Callbacks {
+    // Keeps the projectM instance alive for as long as the playlist exists,
+    // and gives the trampolines access to its event subscribers.
+    projectm: ProjectM,
+    preset_switched: PresetSwitchedSlot,
+    preset_switch_failed: PresetSwitchFailedSlot,
+}
+
+/// A navigation request queued through a [`PlaylistRemote`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistCommand {
+    /// See [`Playlist::play_next`].
+    PlayNext,
+    /// See [`Playlist::play_prev`].
+    PlayPrev,
+    /// See [`Playlist::play_last`].
+    PlayLast,
+    /// See [`Playlist::play_random`].
+    PlayRandom,
+    /// See [`Playlist::set_position`].
+    SetPosition { index: u32, hard_cut: bool },
+    /// See [`Playlist::back`].
+    Back,
+    /// See [`Playlist::forward`].
+    Forward,
+    /// See [`Playlist::set_shuffle`].
+    SetShuffle(bool),
+}
+
+/// Handle for controlling a [`Playlist`] from other threads.
+///
+/// Commands are queued and only take effect once the thread owning the
+/// playlist calls [`Playlist::process_commands`].
+#[derive(Debug, Clone)]
+pub struct PlaylistRemote {
+    sender: mpsc::Sender<PlaylistCommand>,
+}
+
+impl PlaylistRemote {
+    /// Queue a command. Returns `false` if the playlist has been dropped.
+    pub fn send(&self, command: PlaylistCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    pub fn play_next(&self) -> bool {
+        self.send(PlaylistCommand::PlayNext)
+    }
+
+    pub fn play_prev(&self) -> bool {
+        self.send(PlaylistCommand::PlayPrev)
+    }
+
+    pub fn play_random(&self) -> bool {
+        self.send(PlaylistCommand::PlayRandom)
+    }
+
+    pub fn set_position(&self, index: u32, hard_cut: bool) -> bool {
+        self.send(PlaylistCommand::SetPosition { index, hard_cut })
+    }
+}
+
+/// A projectM playlist.
+///
+/// A `Playlist` is neither `Send` nor `Sync`: it drives the projectM
+/// instance it was created from and must stay on that instance's thread.
+/// Other threads can navigate it through a [`PlaylistRemote`].
 pub struct Playlist {
     playlist: *mut ffi::projectm_playlist,
-    rng: rand::rngs::ThreadRng,
+    // Boxed so the address handed to projectM stays stable. Dropped after the
+    // native playlist has been destroyed.
+    callbacks: Box<Callbacks>,
+    rng: Box<dyn RngCore>,
+    // Route shuffled navigation through `rng` instead of libprojectM's own
+    // random number generator.
+    deterministic: bool,
+    shuffle: ShuffleOrder,
+    history: History,
+    commands: mpsc::Receiver<PlaylistCommand>,
+    remote: PlaylistRemote,
 }
 
 impl Playlist {
-    /// Create a new playlist for [Projectm](ProjectMHandle)
-    pub fn create(projectm: &ProjectM) -> Playlist {
-        let projectm = projectm.get_instance();
-        let instance = projectm.borrow_mut();
+    /// Create a new playlist for [ProjectM](crate::core::ProjectM).
+    ///
+    /// The playlist holds its own reference to the instance, so it stays
+    /// valid even if every other [`ProjectM`] handle is dropped first.
+    pub fn create(projectm: &ProjectM) -> Result<Playlist> {
+        Self::create_with_rng(projectm, Box::new(rand::thread_rng()), false)
+    }
+
+    /// Create a new playlist that draws all its randomness from `rng`.
+    ///
+    /// The playlist starts in deterministic mode, see
+    /// [`set_deterministic`](Self::set_deterministic).
+    pub fn with_rng<R: RngCore + 'static>(projectm: &ProjectM, rng: R) -> Result<Playlist> {
+        Self::create_with_rng(projectm, Box::new(rng), true)
+    }
 
+    fn create_with_rng(
+        projectm: &ProjectM,
+        rng: Box<dyn RngCore>,
+        deterministic: bool,
+    ) -> Result<Playlist> {
         let playlist;
         unsafe {
-            playlist = ffi::projectm_playlist_create(*instance);
+            playlist = ffi::projectm_playlist_create(projectm.get_instance());
+        }
+
+        if playlist.is_null() {
+            return Err(Error::NullPlaylist);
         }
-        Playlist {
+
+        let callbacks = Box::new(Callbacks {
+            projectm: projectm.clone(),
+            preset_switched: CallbackSlot::new(),
+            preset_switch_failed: CallbackSlot::new(),
+        });
+
+        Self::register_preset_switched_event_callback(playlist, &callbacks);
+        Self::register_preset_switch_failed_event_callback(playlist, &callbacks);
+
+        let (sender, commands) = mpsc::channel();
+
+        Ok(Playlist {
             playlist,
-            rng: rand::thread_rng(),
+            callbacks,
+            rng,
+            deterministic,
+            shuffle: ShuffleOrder::new(),
+            history: History::new(),
+            commands,
+            remote: PlaylistRemote { sender },
+        })
+    }
+
+    fn register_preset_switched_event_callback(
+        playlist: *mut ffi::projectm_playlist,
+        callbacks: &Callbacks,
+    ) {
+        unsafe extern "C" fn trampoline(
+            is_hard_cut: bool,
+            index: std::os::raw::c_uint,
+            user_data: *mut std::os::raw::c_void,
+        ) {
+            let callbacks = unsafe { &*user_data.cast::<Callbacks>() };
+            callbacks
+                .projectm
+                .event_senders()
+                .send(Event::PlaylistPresetSwitched {
+                    hard_cut: is_hard_cut,
+                    index,
+                });
+            callbacks
+                .preset_switched
+                .call(|callback| callback(is_hard_cut, index));
+        }
+        unsafe {
+            ffi::projectm_playlist_set_preset_switched_event_callback(
+                playlist,
+                Some(trampoline),
+                (callbacks as *const Callbacks as *mut Callbacks).cast::<std::os::raw::c_void>(),
+            )
+        }
+    }
+
+    fn register_preset_switch_failed_event_callback(
+        playlist: *mut ffi::projectm_playlist,
+        callbacks: &Callbacks,
+    ) {
+        unsafe extern "C" fn trampoline(
+            preset_filename: *const std::os::raw::c_char,
+            message: *const std::os::raw::c_char,
+            user_data: *mut std::os::raw::c_void,
+        ) {
+            let callbacks = unsafe { &*user_data.cast::<Callbacks>() };
+            let filename = unsafe { CStr::from_ptr(preset_filename) }
+                .to_string_lossy()
+                .into_owned();
+            let message = unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned();
+            callbacks
+                .projectm
+                .event_senders()
+                .send(Event::PlaylistPresetSwitchFailed {
+                    filename: filename.clone(),
+                    message: message.clone(),
+                });
+            callbacks
+                .preset_switch_failed
+                .call(|callback| callback(filename, message));
+        }
+        unsafe {
+            ffi::projectm_playlist_set_preset_switch_failed_event_callback(
+                playlist,
+                Some(trampoline),
+                (callbacks as *const Callbacks as *mut Callbacks).cast::<std::os::raw::c_void>(),
+            )
         }
     }
 
+    /// Take ownership of a string returned by the playlist library.
+    fn take_string(string: *mut std::os::raw::c_char) -> Result<String> {
+        let result = unsafe { CStr::from_ptr(string) }
+            .to_str()
+            .map(str::to_owned);
+        unsafe { ffi::projectm_playlist_free_string(string) };
+        Ok(result?)
+    }
+
+    /// Take ownership of a NULL-terminated string array returned by the
+    /// playlist library.
+    fn take_string_array(array: *mut *mut std::os::raw::c_char) -> Result<Vec<String>> {
+        if array.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let mut strings = Vec::new();
+        let mut result = Ok(());
+        let mut index = 0;
+        loop {
+            let string = unsafe { *array.add(index) };
+            if string.is_null() {
+                break;
+            }
+            match unsafe { CStr::from_ptr(string) }.to_str() {
+                Ok(string) => strings.push(string.to_owned()),
+                Err(err) if result.is_ok() => result = Err(err),
+                Err(_) => {}
+            }
+            index += 1;
+        }
+
+        unsafe { ffi::projectm_playlist_free_string_array(array) };
+        result?;
+        Ok(strings)
+    }
+
+    fn check_index(&self, index: u32) -> Result<()> {
+        let len = self.len();
+        if index >= len {
+            return Err(Error::IndexOutOfRange { index, len });
+        }
+        Ok(())
+    }
+
+    /// Record a jump in the history.
+    ///
+    /// The position before the first jump is recorded as well, so going back
+    /// after it returns to where playback started.
+    fn record(&mut self, previous: u32, index: u32) {
+        if self.history.current().is_none() {
+            self.history.push(previous);
+        }
+        self.history.push(index);
+    }
+
+    fn check_insert_index(&self, index: u32) -> Result<()> {
+        let len = self.len();
+        if index > len {
+            return Err(Error::IndexOutOfRange { index, len });
+        }
+        Ok(())
+    }
+
     pub fn len(&self) -> u32 {
         unsafe { ffi::projectm_playlist_size(self.playlist) }
     }
@@ -35,36 +643,374 @@ impl Playlist {
         self.len() == 0
     }
 
+    /// Remove all presets from the playlist.
+    pub fn clear(&mut self) {
+        unsafe { ffi::projectm_playlist_clear(self.playlist) };
+        self.history.clear();
+    }
+
+    /// Get the paths of all presets in the playlist.
+    pub fn items(&self) -> Result<Vec<String>> {
+        let items = unsafe { ffi::projectm_playlist_items(self.playlist, 0, self.len()) };
+        Self::take_string_array(items)
+    }
+
+    /// Get the path of the preset at `index`.
+    pub fn item(&self, index: u32) -> Result<String> {
+        self.check_index(index)?;
+
+        let item = unsafe { ffi::projectm_playlist_item(self.playlist, index) };
+        if item.is_null() {
+            return Err(Error::IndexOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        Self::take_string(item)
+    }
+
     /// Scan and add a directory of presets to the playlist.
-    pub fn add_path(&self, path: &str, recursive: bool) {
-        unsafe {
-            let c_path = CString::new(path).unwrap();
-            ffi::projectm_playlist_add_path(self.playlist, c_path.as_ptr(), recursive, false);
+    ///
+    /// Returns the number of presets added.
+    pub fn add_path<P: AsRef<Path>>(&self, path: P, recursive: bool) -> Result<u32> {
+        let c_path = path_to_cstring(path.as_ref())?;
+        let added = unsafe {
+            ffi::projectm_playlist_add_path(self.playlist, c_path.as_ptr(), recursive, false)
+        };
+        Ok(added)
+    }
+
+    /// Scan a directory of presets and insert them at `index`.
+    ///
+    /// Returns the number of presets added.
+    pub fn insert_path<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        index: u32,
+        recursive: bool,
+    ) -> Result<u32> {
+        self.check_insert_index(index)?;
+
+        let c_path = path_to_cstring(path.as_ref())?;
+        let added = unsafe {
+            ffi::projectm_playlist_insert_path(
+                self.playlist,
+                c_path.as_ptr(),
+                index,
+                recursive,
+                false,
+            )
+        };
+        self.history.inserted(index, added);
+        Ok(added)
+    }
+
+    /// Add a single preset to the end of the playlist.
+    ///
+    /// Returns `false` if the preset was not added, e.g. because it is
+    /// already in the playlist and `allow_duplicates` is not set.
+    pub fn add_preset<P: AsRef<Path>>(&self, filename: P, allow_duplicates: bool) -> Result<bool> {
+        let c_filename = path_to_cstring(filename.as_ref())?;
+        let added = unsafe {
+            ffi::projectm_playlist_add_preset(self.playlist, c_filename.as_ptr(), allow_duplicates)
+        };
+        Ok(added)
+    }
+
+    /// Insert a single preset at `index`.
+    pub fn insert_preset<P: AsRef<Path>>(
+        &mut self,
+        filename: P,
+        index: u32,
+        allow_duplicates: bool,
+    ) -> Result<bool> {
+        self.check_insert_index(index)?;
+
+        let c_filename = path_to_cstring(filename.as_ref())?;
+        let added = unsafe {
+            ffi::projectm_playlist_insert_preset(
+                self.playlist,
+                c_filename.as_ptr(),
+                index,
+                allow_duplicates,
+            )
+        };
+        if added {
+            self.history.inserted(index, 1);
+        }
+        Ok(added)
+    }
+
+    /// Add several presets to the end of the playlist.
+    ///
+    /// Returns the number of presets added.
+    pub fn add_presets<P: AsRef<Path>>(
+        &self,
+        filenames: &[P],
+        allow_duplicates: bool,
+    ) -> Result<u32> {
+        let c_filenames = filenames
+            .iter()
+            .map(|filename| path_to_cstring(filename.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut pointers: Vec<_> = c_filenames.iter().map(|f| f.as_ptr()).collect();
+        let count = u32::try_from(pointers.len()).map_err(|_| Error::OutOfRange("preset count"))?;
+
+        let added = unsafe {
+            ffi::projectm_playlist_add_presets(
+                self.playlist,
+                pointers.as_mut_ptr(),
+                count,
+                allow_duplicates,
+            )
+        };
+        Ok(added)
+    }
+
+    /// Insert several presets at `index`.
+    ///
+    /// Returns the number of presets added.
+    pub fn insert_presets<P: AsRef<Path>>(
+        &mut self,
+        filenames: &[P],
+        index: u32,
+        allow_duplicates: bool,
+    ) -> Result<u32> {
+        self.check_insert_index(index)?;
+
+        let c_filenames = filenames
+            .iter()
+            .map(|filename| path_to_cstring(filename.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut pointers: Vec<_> = c_filenames.iter().map(|f| f.as_ptr()).collect();
+        let count = u32::try_from(pointers.len()).map_err(|_| Error::OutOfRange("preset count"))?;
+
+        let added = unsafe {
+            ffi::projectm_playlist_insert_presets(
+                self.playlist,
+                pointers.as_mut_ptr(),
+                count,
+                index,
+                allow_duplicates,
+            )
+        };
+        self.history.inserted(index, added);
+        Ok(added)
+    }
+
+    /// Remove the preset at `index`.
+    pub fn remove_preset(&mut self, index: u32) -> Result<()> {
+        self.check_index(index)?;
+
+        if unsafe { ffi::projectm_playlist_remove_preset(self.playlist, index) } {
+            self.history.removed(index, 1);
         }
+        Ok(())
+    }
+
+    /// Remove up to `count` presets starting at `index`.
+    ///
+    /// Returns the number of presets removed.
+    pub fn remove_presets(&mut self, index: u32, count: u32) -> Result<u32> {
+        self.check_index(index)?;
+
+        let removed = unsafe { ffi::projectm_playlist_remove_presets(self.playlist, index, count) };
+        self.history.removed(index, removed);
+        Ok(removed)
+    }
+
+    /// Sort the whole playlist.
+    ///
+    /// This clears the playlist's history.
+    pub fn sort(&mut self, predicate: SortPredicate, order: SortOrder) {
+        self.sort_range(0, self.len(), predicate, order);
+    }
+
+    /// Sort `count` presets starting at `start`.
+    ///
+    /// This clears the playlist's history.
+    pub fn sort_range(
+        &mut self,
+        start: u32,
+        count: u32,
+        predicate: SortPredicate,
+        order: SortOrder,
+    ) {
+        unsafe {
+            ffi::projectm_playlist_sort(
+                self.playlist,
+                start,
+                count,
+                predicate.to_ffi(),
+                order.to_ffi(),
+            )
+        };
+        self.history.clear();
     }
 
     /// Go to the next preset in the playlist (hard cut).
+    ///
+    /// Does nothing on an empty playlist.
     pub fn play_next(&mut self) {
-        unsafe {
-            ffi::projectm_playlist_play_next(self.playlist, true);
+        if self.deterministic && self.get_shuffle() {
+            self.play_shuffled();
+            return;
         }
+
+        let previous = self.get_position();
+        let index = unsafe { ffi::projectm_playlist_play_next(self.playlist, true) };
+        self.record(previous, index);
     }
 
-    /// Go to the previous preset in the playlist (hard cut).
+    /// Go back to the previously visited preset (hard cut).
+    ///
+    /// Follows the playlist's history like a browser's back button. If
+    /// there is no earlier entry, this goes to the preceding preset in the
+    /// playlist instead. Does nothing on an empty playlist.
     pub fn play_prev(&mut self) {
-        unsafe {
-            // FIXME THIS IS WRONG
-            ffi::projectm_playlist_play_previous(self.playlist, true);
+        if self.back().is_none() {
+            if self.deterministic && self.get_shuffle() {
+                self.play_shuffled();
+                return;
+            }
+
+            let previous = self.get_position();
+            let index = unsafe { ffi::projectm_playlist_play_previous(self.playlist, true) };
+            self.record(previous, index);
         }
     }
 
+    /// Go back to the last preset played by libprojectM (hard cut).
+    pub fn play_last(&mut self) {
+        let previous = self.get_position();
+        let index = unsafe { ffi::projectm_playlist_play_last(self.playlist, true) };
+        self.record(previous, index);
+    }
+
     /// Go to a random preset in the playlist (hard cut).
-    pub fn play_random(&mut self) {
+    ///
+    /// Every call draws independently, so the same preset may come up
+    /// twice in a row. For a shuffle that plays every preset once, use
+    /// [`play_next`](Self::play_next) with shuffle and deterministic mode
+    /// enabled.
+    pub fn play_random(&mut self) -> Result<()> {
         let len = self.len();
+        if len == 0 {
+            return Err(Error::EmptyPlaylist);
+        }
+
         let index: u32 = self.rng.gen_range(0..len);
+        self.set_position(index, true)
+    }
+
+    /// Shuffled navigation in deterministic mode.
+    ///
+    /// Like libprojectM's own navigation, this is a no-op on an empty
+    /// playlist rather than an error.
+    fn play_shuffled(&mut self) {
+        let len = self.len();
+        if len == 0 {
+            return;
+        }
+        let index = self
+            .shuffle
+            .next(len, self.get_position(), self.rng.as_mut());
+        // The order only holds indices below the current length.
+        self.set_position(index, true)
+            .expect("shuffled index is within the playlist");
+    }
+
+    /// Go to the preset at `index` in the playlist.
+    pub fn set_position(&mut self, index: u32, hard_cut: bool) -> Result<()> {
+        self.check_index(index)?;
+
+        let previous = self.get_position();
+        unsafe {
+            ffi::projectm_playlist_set_position(self.playlist, index, hard_cut);
+        }
+        self.record(previous, index);
+        Ok(())
+    }
+
+    /// Replace the random number generator.
+    pub fn set_rng<R: RngCore + 'static>(&mut self, rng: R) {
+        self.rng = Box::new(rng);
+        self.shuffle.reset();
+    }
+
+    /// Seed a portable random number generator and enable deterministic
+    /// mode.
+    ///
+    /// The same seed followed by the same sequence of calls always visits
+    /// the same presets, on every platform.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Box::new(ChaCha8Rng::seed_from_u64(seed));
+        self.shuffle.reset();
+        self.deterministic = true;
+    }
+
+    /// Set deterministic mode.
+    ///
+    /// When enabled, shuffled [`play_next`](Self::play_next) and
+    /// [`play_prev`](Self::play_prev) pick presets with this playlist's
+    /// random number generator instead of libprojectM's. They walk a
+    /// permutation of the playlist, so every preset plays once before any
+    /// repeats, and draw a new permutation when it is used up or the
+    /// playlist size changed. Automatic preset switches made by
+    /// libprojectM itself are not affected.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Get deterministic mode.
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Step back in the history (hard cut).
+    ///
+    /// Returns the new position, or `None` if there is no earlier entry.
+    pub fn back(&mut self) -> Option<u32> {
+        let index = self.history.back()?;
         unsafe {
             ffi::projectm_playlist_set_position(self.playlist, index, true);
         }
+        Some(index)
+    }
+
+    /// Step forward in the history after going [`back`](Self::back).
+    ///
+    /// Returns the new position, or `None` if there is no later entry.
+    pub fn forward(&mut self) -> Option<u32> {
+        let index = self.history.forward()?;
+        unsafe {
+            ffi::projectm_playlist_set_position(self.playlist, index, true);
+        }
+        Some(index)
+    }
+
+    /// Positions visited through this playlist, oldest first.
+    ///
+    /// Only jumps made through `Playlist` methods are recorded; automatic
+    /// preset switches by projectM are not. Sorting, clearing or filtering
+    /// the playlist clears the history.
+    pub fn history(&self) -> &[u32] {
+        &self.history.entries
+    }
+
+    /// Index into [`history`](Self::history) of the current entry.
+    pub fn history_cursor(&self) -> Option<usize> {
+        self.history.current().map(|_| self.history.cursor)
+    }
+
+    /// Forget all visited positions.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Get the index of the current preset.
+    pub fn get_position(&self) -> u32 {
+        unsafe { ffi::projectm_playlist_get_position(self.playlist) }
     }
 
     /// Set shuffle mode.
@@ -78,7 +1024,133 @@ impl Playlist {
     pub fn get_shuffle(&self) -> bool {
         unsafe { ffi::projectm_playlist_get_shuffle(self.playlist) }
     }
+
+    /// Set how many presets are tried before giving up when loading fails.
+    pub fn set_retry_count(&self, retry_count: u32) {
+        unsafe { ffi::projectm_playlist_set_retry_count(self.playlist, retry_count) };
+    }
+
+    /// Get how many presets are tried before giving up when loading fails.
+    pub fn get_retry_count(&self) -> u32 {
+        unsafe { ffi::projectm_playlist_get_retry_count(self.playlist) }
+    }
+
+    /// Set the callback invoked after the playlist switched presets.
+    ///
+    /// The callback receives whether it was a hard cut and the new index.
+    /// Any previously set callback is dropped.
+    pub fn set_preset_switched_event_callback<F: FnMut(bool, u32) + 'static>(&self, callback: F) {
+        self.callbacks.preset_switched.set(Box::new(callback));
+    }
+
+    /// Remove the preset switched callback.
+    pub fn clear_preset_switched_event_callback(&self) {
+        self.callbacks.preset_switched.clear();
+    }
+
+    /// Set the callback invoked when the playlist failed to load a preset.
+    ///
+    /// Any previously set callback is dropped.
+    pub fn set_preset_switch_failed_event_callback<F: FnMut(String, String) + 'static>(
+        &self,
+        callback: F,
+    ) {
+        self.callbacks.preset_switch_failed.set(Box::new(callback));
+    }
+
+    /// Remove the preset switch failed callback.
+    pub fn clear_preset_switch_failed_event_callback(&self) {
+        self.callbacks.preset_switch_failed.clear();
+    }
+
+    /// Set the playlist's filter list.
+    ///
+    /// Each entry is a glob pattern, optionally prefixed with `+` to keep or
+    /// `-` to remove matching presets. The filter applies to presets added
+    /// afterwards; call [`apply_filter`](Self::apply_filter) to also filter
+    /// the current items.
+    pub fn set_filter<S: AsRef<str>>(&self, filter: &[S]) -> Result<()> {
+        let c_filter = filter
+            .iter()
+            .map(|entry| CString::new(entry.as_ref()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let mut pointers: Vec<_> = c_filter.iter().map(|f| f.as_ptr()).collect();
+
+        unsafe {
+            ffi::projectm_playlist_set_filter(self.playlist, pointers.as_mut_ptr(), pointers.len())
+        };
+        Ok(())
+    }
+
+    /// Get the playlist's filter list.
+    pub fn get_filter(&self) -> Result<Vec<String>> {
+        let mut count = 0;
+        let filter = unsafe { ffi::projectm_playlist_get_filter(self.playlist, &mut count) };
+        Self::take_string_array(filter)
+    }
+
+    /// Apply the filter list to the current items.
+    ///
+    /// Returns the number of presets removed. This clears the playlist's
+    /// history if any preset was removed.
+    pub fn apply_filter(&mut self) -> usize {
+        let removed = unsafe { ffi::projectm_playlist_apply_filter(self.playlist) };
+        if removed > 0 {
+            self.history.clear();
+        }
+        removed
+    }
+}
+
+impl Playlist {
+    /// Get a handle through which other threads can queue commands.
+    pub fn remote(&self) -> PlaylistRemote {
+        self.remote.clone()
+    }
+
+    /// Run a single command.
+    pub fn apply(&mut self, command: PlaylistCommand) -> Result<()> {
+        match command {
+            PlaylistCommand::PlayNext => self.play_next(),
+            PlaylistCommand::PlayPrev => self.play_prev(),
+            PlaylistCommand::PlayLast => self.play_last(),
+            PlaylistCommand::PlayRandom => self.play_random()?,
+            PlaylistCommand::SetPosition { index, hard_cut } => {
+                self.set_position(index, hard_cut)?
+            }
+            PlaylistCommand::Back => {
+                self.back();
+            }
+            PlaylistCommand::Forward => {
+                self.forward();
+            }
+            PlaylistCommand::SetShuffle(shuffle) => self.set_shuffle(shuffle),
+        }
+        Ok(())
+    }
+
+    /// Run the commands queued through [`PlaylistRemote`]s, in order.
+    ///
+    /// Call this regularly from the thread owning the playlist, e.g. once
+    /// per frame. Returns the number of commands run. If a command fails,
+    /// its error is returned and the remaining commands stay queued.
+    pub fn process_commands(&mut self) -> Result<usize> {
+        let mut processed = 0;
+        while let Ok(command) = self.commands.try_recv() {
+            processed += 1;
+            self.apply(command)?;
+        }
+        Ok(processed)
+    }
 }
 
-unsafe impl Send for Playlist {}
-unsafe impl Sync for Playlist {}
+impl Drop for Playlist {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::projectm_playlist_destroy(self.playlist);
+        }
+        // Destroying the playlist clears projectM's preset switch callbacks,
+        // which the playlist had taken over.
+        self.callbacks.projectm.register_event_callbacks();
+    }
+}
@@ -0,0 +1,942 @@
+//! Parsing of MilkDrop `.milk` preset files.
+//!
+//! [`Preset::parse`] reads a preset into typed sections (parameters,
+//! equations, custom waves and shapes, shaders) without involving
+//! libprojectM. Every parsed item keeps the [`Span`] of the line it came
+//! from, so tools can point back into the source, and
+//! [`Preset::to_milk`] writes it back without disturbing the author's
+//! formatting. Equation code is parsed by the [`expr`] module and can be
+//! run without a GPU by the [`eval`] module; [`lint`] checks a preset for
+//! common mistakes.
+
+pub mod eval;
+pub mod expr;
+pub mod lint;
+
+pub use self::lint::{lint, lint_source};
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// A byte range in a preset's source text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub const fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// The section header, `[preset00]` in every MilkDrop preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    pub line: usize,
+    pub span: Span,
+}
+
+/// A `key=value` setting such as `fDecay=0.98`.
+///
+/// Items built in code rather than parsed have line 0 and empty spans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+    pub span: Span,
+    pub key_span: Span,
+    pub value_span: Span,
+}
+
+impl Parameter {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Parameter {
+            key: key.into(),
+            value: value.into(),
+            line: 0,
+            span: Span::default(),
+            key_span: Span::default(),
+            value_span: Span::default(),
+        }
+    }
+
+    /// The value as a number, read the way MilkDrop does: the longest
+    /// numeric prefix counts and anything after it is ignored, so `0;`
+    /// reads as 0.
+    pub fn as_f64(&self) -> Option<f64> {
+        parse_number_prefix(&self.value)
+    }
+}
+
+/// One numbered line of equation or shader code, e.g. `per_frame_1=...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    pub index: u32,
+    pub code: String,
+    pub line: usize,
+    pub span: Span,
+    pub code_span: Span,
+}
+
+impl Equation {
+    pub fn new(index: u32, code: impl Into<String>) -> Self {
+        Equation {
+            index,
+            code: code.into(),
+            line: 0,
+            span: Span::default(),
+            code_span: Span::default(),
+        }
+    }
+
+    /// The lines MilkDrop actually loads, in index order.
+    ///
+    /// MilkDrop reads `_1`, `_2`, ... and stops at the first missing index,
+    /// so lines after a gap are dropped. Of several lines with the same
+    /// index, only the first in the file is kept.
+    pub fn loaded(equations: &[Equation]) -> Vec<&Equation> {
+        let mut sorted: Vec<&Equation> = equations.iter().collect();
+        sorted.sort_by_key(|equation| (equation.index, equation.line));
+
+        let mut loaded = Vec::with_capacity(sorted.len());
+        let mut expected = 1;
+        for equation in sorted {
+            if equation.index < expected {
+                continue;
+            }
+            if equation.index > expected {
+                break;
+            }
+            loaded.push(equation);
+            expected += 1;
+        }
+        loaded
+    }
+
+    /// Join the [loaded](Equation::loaded) lines into one block of code,
+    /// the way MilkDrop concatenates them before compiling.
+    pub fn join(equations: &[Equation]) -> String {
+        let lines: Vec<&str> = Equation::loaded(equations)
+            .iter()
+            .map(|e| e.code.as_str())
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// A custom waveform, from the `wavecode_N_*` and `wave_N_*` keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomWave {
+    pub index: u32,
+    /// Settings from `wavecode_N_<key>`, keyed by `<key>`.
+    pub parameters: Vec<Parameter>,
+    pub init: Vec<Equation>,
+    pub per_frame: Vec<Equation>,
+    pub per_point: Vec<Equation>,
+}
+
+impl CustomWave {
+    pub fn new(index: u32) -> Self {
+        CustomWave {
+            index,
+            ..Default::default()
+        }
+    }
+
+    /// Look up a `wavecode_N_<key>` setting by `<key>`, ignoring case.
+    pub fn get(&self, key: &str) -> Option<&Parameter> {
+        find_parameter(&self.parameters, key)
+    }
+}
+
+/// A custom shape, from the `shapecode_N_*` and `shape_N_*` keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CustomShape {
+    pub index: u32,
+    /// Settings from `shapecode_N_<key>`, keyed by `<key>`.
+    pub parameters: Vec<Parameter>,
+    pub init: Vec<Equation>,
+    pub per_frame: Vec<Equation>,
+}
+
+impl CustomShape {
+    pub fn new(index: u32) -> Self {
+        CustomShape {
+            index,
+            ..Default::default()
+        }
+    }
+
+    /// Look up a `shapecode_N_<key>` setting by `<key>`, ignoring case.
+    pub fn get(&self, key: &str) -> Option<&Parameter> {
+        find_parameter(&self.parameters, key)
+    }
+}
+
+/// A `//` comment on a line of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub span: Span,
+}
+
+/// What a line of the source contained.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineKind {
+    Blank,
+    Comment,
+    Header,
+    Parameter,
+    PerFrameInit,
+    PerFrame,
+    PerPixel,
+    WarpShader,
+    CompShader,
+    WaveParameter { wave: u32 },
+    WaveInit { wave: u32 },
+    WavePerFrame { wave: u32 },
+    WavePerPoint { wave: u32 },
+    ShapeParameter { shape: u32 },
+    ShapeInit { shape: u32 },
+    ShapePerFrame { shape: u32 },
+}
+
+/// A physical line of the source, without its line terminator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    /// 1-based line number.
+    pub number: usize,
+    pub span: Span,
+    pub kind: LineKind,
+}
+
+/// A parsed `.milk` preset.
+///
+/// Equations are kept in file order, including lines MilkDrop skips; use
+/// [`Equation::join`] or the `*_code` helpers to get the code MilkDrop
+/// runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Preset {
+    pub header: Option<Header>,
+    pub parameters: Vec<Parameter>,
+    pub per_frame_init: Vec<Equation>,
+    pub per_frame: Vec<Equation>,
+    pub per_pixel: Vec<Equation>,
+    /// Custom waves, sorted by index.
+    pub waves: Vec<CustomWave>,
+    /// Custom shapes, sorted by index.
+    pub shapes: Vec<CustomShape>,
+    pub warp_shader: Vec<Equation>,
+    pub comp_shader: Vec<Equation>,
+    pub comments: Vec<Comment>,
+    source: String,
+    lines: Vec<Line>,
+}
+
+impl Preset {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the text of a `.milk` file.
+    ///
+    /// Lines that are neither blank, a `//` comment, a `[section]` header
+    /// nor `key=value` are rejected with [`Error::PresetSyntax`].
+    pub fn parse(source: &str) -> Result<Preset> {
+        let mut parser = Parser {
+            source,
+            preset: Preset {
+                source: source.to_string(),
+                ..Default::default()
+            },
+        };
+
+        let mut start = 0;
+        let mut number = 0;
+        while start < source.len() {
+            let end = source[start..]
+                .find('\n')
+                .map_or(source.len(), |offset| start + offset);
+            let mut text_end = end;
+            if source[start..end].ends_with('\r') {
+                text_end -= 1;
+            }
+
+            number += 1;
+            parser.line(number, Span::new(start, text_end))?;
+            start = end + 1;
+        }
+
+        Ok(parser.preset)
+    }
+
+    /// Read and parse a `.milk` file.
+    ///
+    /// The file is decoded with [`decode`], so presets saved in a legacy
+    /// Windows code page load as well.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Preset> {
+        Preset::parse(&decode(&std::fs::read(path)?))
+    }
+
+    /// The text this preset was parsed from, or an empty string for a
+    /// preset built in code.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Every line of the source in order, including blank lines.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Look up a top-level setting, ignoring case as MilkDrop does. If a
+    /// key appears more than once the first occurrence wins.
+    pub fn get(&self, key: &str) -> Option<&Parameter> {
+        find_parameter(&self.parameters, key)
+    }
+
+    /// The numeric value of a top-level setting.
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        self.get(key).and_then(Parameter::as_f64)
+    }
+
+    pub fn wave(&self, index: u32) -> Option<&CustomWave> {
+        self.waves.iter().find(|wave| wave.index == index)
+    }
+
+    pub fn shape(&self, index: u32) -> Option<&CustomShape> {
+        self.shapes.iter().find(|shape| shape.index == index)
+    }
+
+    pub fn per_frame_init_code(&self) -> String {
+        Equation::join(&self.per_frame_init)
+    }
+
+    pub fn per_frame_code(&self) -> String {
+        Equation::join(&self.per_frame)
+    }
+
+    pub fn per_pixel_code(&self) -> String {
+        Equation::join(&self.per_pixel)
+    }
+
+    /// The warp shader, with the leading backtick of each line removed.
+    pub fn warp_shader_code(&self) -> String {
+        shader_code(&self.warp_shader)
+    }
+
+    /// The composite shader, with the leading backtick of each line
+    /// removed.
+    pub fn comp_shader_code(&self) -> String {
+        shader_code(&self.comp_shader)
+    }
+
+    /// Write the preset back out in `.milk` format.
+    ///
+    /// Lines whose item is unchanged since parsing are copied from the
+    /// source byte for byte, including comments, blank lines and line
+    /// endings. Edited items are written in canonical `key=value` form in
+    /// their original place, removed items are dropped and new items are
+    /// appended after the existing lines, grouped by section.
+    pub fn to_milk(&self) -> String {
+        let source = self.source.as_str();
+        let newline = if source.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut items = self.items();
+        let mut emitted = vec![false; items.len()];
+        let mut out = String::with_capacity(source.len());
+
+        for (position, line) in self.lines.iter().enumerate() {
+            let next_start = self
+                .lines
+                .get(position + 1)
+                .map_or(source.len(), |next| next.span.start);
+            let terminator = &source[line.span.end..next_start];
+
+            if line.kind == LineKind::Blank {
+                out.push_str(&source[line.span.start..next_start]);
+                continue;
+            }
+
+            let found = items.iter().enumerate().position(|(i, item)| {
+                !emitted[i] && item.kind == line.kind && item.origin() == Some(line.span)
+            });
+            let Some(i) = found else {
+                continue;
+            };
+            emitted[i] = true;
+
+            if items[i].is_unchanged(source) {
+                out.push_str(&source[line.span.start..next_start]);
+            } else {
+                out.push_str(&items[i].render());
+                out.push_str(terminator);
+            }
+        }
+
+        for (i, item) in items.drain(..).enumerate() {
+            if emitted[i] {
+                continue;
+            }
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push_str(newline);
+            }
+            out.push_str(&item.render());
+            out.push_str(newline);
+        }
+
+        out
+    }
+
+    /// Write the preset to a `.milk` file, see [`Preset::to_milk`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_milk())?;
+        Ok(())
+    }
+
+    /// Every item of the preset in canonical file order.
+    fn items(&self) -> Vec<Item<'_>> {
+        let mut items = Vec::new();
+        if let Some(header) = &self.header {
+            items.push(Item::new(LineKind::Header, ItemRef::Header(header)));
+        }
+        for parameter in &self.parameters {
+            items.push(Item::new(
+                LineKind::Parameter,
+                ItemRef::Parameter(parameter),
+            ));
+        }
+        push_equations(&mut items, LineKind::PerFrameInit, &self.per_frame_init);
+        push_equations(&mut items, LineKind::PerFrame, &self.per_frame);
+        push_equations(&mut items, LineKind::PerPixel, &self.per_pixel);
+        for wave in &self.waves {
+            let index = wave.index;
+            for parameter in &wave.parameters {
+                let kind = LineKind::WaveParameter { wave: index };
+                items.push(Item::new(kind, ItemRef::Parameter(parameter)));
+            }
+            push_equations(&mut items, LineKind::WaveInit { wave: index }, &wave.init);
+            push_equations(
+                &mut items,
+                LineKind::WavePerFrame { wave: index },
+                &wave.per_frame,
+            );
+            push_equations(
+                &mut items,
+                LineKind::WavePerPoint { wave: index },
+                &wave.per_point,
+            );
+        }
+        for shape in &self.shapes {
+            let index = shape.index;
+            for parameter in &shape.parameters {
+                let kind = LineKind::ShapeParameter { shape: index };
+                items.push(Item::new(kind, ItemRef::Parameter(parameter)));
+            }
+            push_equations(
+                &mut items,
+                LineKind::ShapeInit { shape: index },
+                &shape.init,
+            );
+            push_equations(
+                &mut items,
+                LineKind::ShapePerFrame { shape: index },
+                &shape.per_frame,
+            );
+        }
+        push_equations(&mut items, LineKind::WarpShader, &self.warp_shader);
+        push_equations(&mut items, LineKind::CompShader, &self.comp_shader);
+        for comment in &self.comments {
+            items.push(Item::new(LineKind::Comment, ItemRef::Comment(comment)));
+        }
+
+        items
+    }
+
+    fn wave_mut(&mut self, index: u32) -> &mut CustomWave {
+        let position = match self.waves.binary_search_by_key(&index, |wave| wave.index) {
+            Ok(position) => position,
+            Err(position) => {
+                self.waves.insert(position, CustomWave::new(index));
+                position
+            }
+        };
+        &mut self.waves[position]
+    }
+
+    fn shape_mut(&mut self, index: u32) -> &mut CustomShape {
+        let position = match self
+            .shapes
+            .binary_search_by_key(&index, |shape| shape.index)
+        {
+            Ok(position) => position,
+            Err(position) => {
+                self.shapes.insert(position, CustomShape::new(index));
+                position
+            }
+        };
+        &mut self.shapes[position]
+    }
+}
+
+impl FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Preset::parse(s)
+    }
+}
+
+/// The meaning of a `key` in a `key=value` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Parameter,
+    PerFrameInit(u32),
+    PerFrame(u32),
+    PerPixel(u32),
+    WarpShader(u32),
+    CompShader(u32),
+    /// The parameter name starts at the given byte offset of the key.
+    WaveParameter(u32, usize),
+    WaveInit(u32, u32),
+    WavePerFrame(u32, u32),
+    WavePerPoint(u32, u32),
+    ShapeParameter(u32, usize),
+    ShapeInit(u32, u32),
+    ShapePerFrame(u32, u32),
+}
+
+impl Key {
+    fn classify(key: &str) -> Key {
+        let lower = key.to_ascii_lowercase();
+        let key = lower.as_str();
+
+        if let Some(index) = key.strip_prefix("per_frame_init_").and_then(parse_index) {
+            return Key::PerFrameInit(index);
+        }
+        if let Some(index) = key.strip_prefix("per_frame_").and_then(parse_index) {
+            return Key::PerFrame(index);
+        }
+        if let Some(index) = key.strip_prefix("per_pixel_").and_then(parse_index) {
+            return Key::PerPixel(index);
+        }
+        if let Some(index) = key.strip_prefix("warp_").and_then(parse_index) {
+            return Key::WarpShader(index);
+        }
+        if let Some(index) = key.strip_prefix("comp_").and_then(parse_index) {
+            return Key::CompShader(index);
+        }
+        if let Some((wave, name)) = key.strip_prefix("wavecode_").and_then(split_index) {
+            return Key::WaveParameter(wave, key.len() - name.len());
+        }
+        if let Some((shape, name)) = key.strip_prefix("shapecode_").and_then(split_index) {
+            return Key::ShapeParameter(shape, key.len() - name.len());
+        }
+        if let Some((wave, rest)) = key.strip_prefix("wave_").and_then(split_index) {
+            if let Some(index) = rest.strip_prefix("init").and_then(parse_index) {
+                return Key::WaveInit(wave, index);
+            }
+            if let Some(index) = rest.strip_prefix("per_frame").and_then(parse_index) {
+                return Key::WavePerFrame(wave, index);
+            }
+            if let Some(index) = rest.strip_prefix("per_point").and_then(parse_index) {
+                return Key::WavePerPoint(wave, index);
+            }
+        }
+        if let Some((shape, rest)) = key.strip_prefix("shape_").and_then(split_index) {
+            if let Some(index) = rest.strip_prefix("init").and_then(parse_index) {
+                return Key::ShapeInit(shape, index);
+            }
+            if let Some(index) = rest.strip_prefix("per_frame").and_then(parse_index) {
+                return Key::ShapePerFrame(shape, index);
+            }
+        }
+
+        Key::Parameter
+    }
+
+    /// The line number of an equation key, e.g. 3 for `per_frame_3`.
+    fn equation_index(self) -> Option<u32> {
+        match self {
+            Key::PerFrameInit(index)
+            | Key::PerFrame(index)
+            | Key::PerPixel(index)
+            | Key::WarpShader(index)
+            | Key::CompShader(index)
+            | Key::WaveInit(_, index)
+            | Key::WavePerFrame(_, index)
+            | Key::WavePerPoint(_, index)
+            | Key::ShapeInit(_, index)
+            | Key::ShapePerFrame(_, index) => Some(index),
+            Key::Parameter | Key::WaveParameter(..) | Key::ShapeParameter(..) => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    preset: Preset,
+}
+
+impl Parser<'_> {
+    fn line(&mut self, number: usize, span: Span) -> Result<()> {
+        let kind = self.classify_line(number, span)?;
+        self.preset.lines.push(Line { number, span, kind });
+        Ok(())
+    }
+
+    fn classify_line(&mut self, number: usize, span: Span) -> Result<LineKind> {
+        let text = &self.source[span.range()];
+        let trimmed = text.trim();
+        let syntax_error = |message: String| Error::PresetSyntax {
+            line: number,
+            message,
+        };
+
+        if trimmed.is_empty() {
+            return Ok(LineKind::Blank);
+        }
+
+        if trimmed.starts_with("//") {
+            self.preset.comments.push(Comment {
+                text: trimmed.to_string(),
+                line: number,
+                span,
+            });
+            return Ok(LineKind::Comment);
+        }
+
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            if self.preset.header.is_some() {
+                return Err(syntax_error(format!(
+                    "unexpected second section header `{trimmed}`"
+                )));
+            }
+            self.preset.header = Some(Header {
+                name: name.to_string(),
+                line: number,
+                span,
+            });
+            return Ok(LineKind::Header);
+        }
+
+        let Some(equals) = text.find('=') else {
+            return Err(syntax_error(format!(
+                "expected `key=value`, found `{trimmed}`"
+            )));
+        };
+
+        let raw_key = &text[..equals];
+        let key = raw_key.trim();
+        if key.is_empty() {
+            return Err(syntax_error("missing key before `=`".to_string()));
+        }
+
+        let key_start = span.start + (raw_key.len() - raw_key.trim_start().len());
+        let key_span = Span::new(key_start, key_start + key.len());
+        let value_span = Span::new(span.start + equals + 1, span.end);
+        let value = &text[equals + 1..];
+
+        let parameter = |key: &str, key_span: Span| Parameter {
+            key: key.to_string(),
+            value: value.to_string(),
+            line: number,
+            span,
+            key_span,
+            value_span,
+        };
+        let equation = |index: u32| Equation {
+            index,
+            code: value.to_string(),
+            line: number,
+            span,
+            code_span: value_span,
+        };
+
+        let preset = &mut self.preset;
+        let kind = match Key::classify(key) {
+            Key::Parameter => {
+                preset.parameters.push(parameter(key, key_span));
+                LineKind::Parameter
+            }
+            Key::PerFrameInit(index) => {
+                preset.per_frame_init.push(equation(index));
+                LineKind::PerFrameInit
+            }
+            Key::PerFrame(index) => {
+                preset.per_frame.push(equation(index));
+                LineKind::PerFrame
+            }
+            Key::PerPixel(index) => {
+                preset.per_pixel.push(equation(index));
+                LineKind::PerPixel
+            }
+            Key::WarpShader(index) => {
+                preset.warp_shader.push(equation(index));
+                LineKind::WarpShader
+            }
+            Key::CompShader(index) => {
+                preset.comp_shader.push(equation(index));
+                LineKind::CompShader
+            }
+            Key::WaveParameter(wave, offset) => {
+                let name_span = Span::new(key_span.start + offset, key_span.end);
+                let parameter = parameter(&key[offset..], name_span);
+                preset.wave_mut(wave).parameters.push(parameter);
+                LineKind::WaveParameter { wave }
+            }
+            Key::WaveInit(wave, index) => {
+                preset.wave_mut(wave).init.push(equation(index));
+                LineKind::WaveInit { wave }
+            }
+            Key::WavePerFrame(wave, index) => {
+                preset.wave_mut(wave).per_frame.push(equation(index));
+                LineKind::WavePerFrame { wave }
+            }
+            Key::WavePerPoint(wave, index) => {
+                preset.wave_mut(wave).per_point.push(equation(index));
+                LineKind::WavePerPoint { wave }
+            }
+            Key::ShapeParameter(shape, offset) => {
+                let name_span = Span::new(key_span.start + offset, key_span.end);
+                let parameter = parameter(&key[offset..], name_span);
+                preset.shape_mut(shape).parameters.push(parameter);
+                LineKind::ShapeParameter { shape }
+            }
+            Key::ShapeInit(shape, index) => {
+                preset.shape_mut(shape).init.push(equation(index));
+                LineKind::ShapeInit { shape }
+            }
+            Key::ShapePerFrame(shape, index) => {
+                preset.shape_mut(shape).per_frame.push(equation(index));
+                LineKind::ShapePerFrame { shape }
+            }
+        };
+
+        Ok(kind)
+    }
+}
+
+/// A borrowed item of a preset, tagged with the kind of line it is
+/// written as.
+struct Item<'a> {
+    kind: LineKind,
+    value: ItemRef<'a>,
+}
+
+#[derive(Clone, Copy)]
+enum ItemRef<'a> {
+    Header(&'a Header),
+    Comment(&'a Comment),
+    Parameter(&'a Parameter),
+    Equation(&'a Equation),
+}
+
+impl<'a> Item<'a> {
+    fn new(kind: LineKind, value: ItemRef<'a>) -> Self {
+        Item { kind, value }
+    }
+
+    /// The span of the line the item was parsed from, if any.
+    fn origin(&self) -> Option<Span> {
+        let (line, span) = match self.value {
+            ItemRef::Header(header) => (header.line, header.span),
+            ItemRef::Comment(comment) => (comment.line, comment.span),
+            ItemRef::Parameter(parameter) => (parameter.line, parameter.span),
+            ItemRef::Equation(equation) => (equation.line, equation.span),
+        };
+        (line != 0).then_some(span)
+    }
+
+    /// Whether the item still matches the line it was parsed from.
+    fn is_unchanged(&self, source: &str) -> bool {
+        let text = |span: Span| source.get(span.range());
+
+        match self.value {
+            ItemRef::Header(header) => {
+                text(header.span).map(str::trim) == Some(format!("[{}]", header.name).as_str())
+            }
+            ItemRef::Comment(comment) => {
+                text(comment.span).map(str::trim) == Some(comment.text.as_str())
+            }
+            ItemRef::Parameter(parameter) => {
+                text(parameter.key_span) == Some(parameter.key.as_str())
+                    && text(parameter.value_span) == Some(parameter.value.as_str())
+            }
+            ItemRef::Equation(equation) => {
+                let key = equation
+                    .code_span
+                    .start
+                    .checked_sub(1)
+                    .and_then(|key_end| source.get(equation.span.start..key_end));
+                let index = key.and_then(|key| Key::classify(key.trim()).equation_index());
+
+                index == Some(equation.index)
+                    && text(equation.code_span) == Some(equation.code.as_str())
+            }
+        }
+    }
+
+    /// The item as a canonical line, without a line terminator.
+    fn render(&self) -> String {
+        match (self.kind, self.value) {
+            (_, ItemRef::Header(header)) => format!("[{}]", header.name),
+            (_, ItemRef::Comment(comment)) => comment.text.clone(),
+            (LineKind::WaveParameter { wave }, ItemRef::Parameter(parameter)) => {
+                format!("wavecode_{wave}_{}={}", parameter.key, parameter.value)
+            }
+            (LineKind::ShapeParameter { shape }, ItemRef::Parameter(parameter)) => {
+                format!("shapecode_{shape}_{}={}", parameter.key, parameter.value)
+            }
+            (_, ItemRef::Parameter(parameter)) => {
+                format!("{}={}", parameter.key, parameter.value)
+            }
+            (kind, ItemRef::Equation(equation)) => {
+                let index = equation.index;
+                let key = match kind {
+                    LineKind::PerFrameInit => format!("per_frame_init_{index}"),
+                    LineKind::PerFrame => format!("per_frame_{index}"),
+                    LineKind::PerPixel => format!("per_pixel_{index}"),
+                    LineKind::WarpShader => format!("warp_{index}"),
+                    LineKind::CompShader => format!("comp_{index}"),
+                    LineKind::WaveInit { wave } => format!("wave_{wave}_init{index}"),
+                    LineKind::WavePerFrame { wave } => format!("wave_{wave}_per_frame{index}"),
+                    LineKind::WavePerPoint { wave } => format!("wave_{wave}_per_point{index}"),
+                    LineKind::ShapeInit { shape } => format!("shape_{shape}_init{index}"),
+                    LineKind::ShapePerFrame { shape } => {
+                        format!("shape_{shape}_per_frame{index}")
+                    }
+                    _ => unreachable!("{kind:?} lines do not hold equations"),
+                };
+                format!("{key}={}", equation.code)
+            }
+        }
+    }
+}
+
+/// Decode the bytes of a `.milk` file.
+///
+/// A leading UTF-8 byte order mark is dropped. Many presets were written
+/// on Windows in a legacy code page, so text that is not valid UTF-8 is
+/// read as Latin-1 instead of being rejected.
+pub fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&byte| char::from(byte)).collect(),
+    }
+}
+
+fn push_equations<'a>(items: &mut Vec<Item<'a>>, kind: LineKind, equations: &'a [Equation]) {
+    for equation in equations {
+        items.push(Item::new(kind, ItemRef::Equation(equation)));
+    }
+}
+
+fn find_parameter<'a>(parameters: &'a [Parameter], key: &str) -> Option<&'a Parameter> {
+    parameters
+        .iter()
+        .find(|parameter| parameter.key.eq_ignore_ascii_case(key))
+}
+
+fn shader_code(lines: &[Equation]) -> String {
+    let lines: Vec<&str> = Equation::loaded(lines)
+        .iter()
+        .map(|line| line.code.strip_prefix('`').unwrap_or(&line.code))
+        .collect();
+    lines.join("\n")
+}
+
+/// Parse a non-empty run of ASCII digits.
+fn parse_index(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Split `N_rest` into `N` and a non-empty `rest`.
+fn split_index(s: &str) -> Option<(u32, &str)> {
+    let (index, rest) = s.split_once('_')?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some((parse_index(index)?, rest))
+}
+
+/// Parse the longest prefix of `s` that is a decimal number, like C's
+/// `atof` but returning `None` when there is no number at all.
+pub(crate) fn parse_number_prefix(s: &str) -> Option<f64> {
+    let s = s.trim_start();
+    let bytes = s.as_bytes();
+    let mut end = 0;
+
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+    }
+    let mut digits = end - digits_start;
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        let fraction_start = end;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        digits += end - fraction_start;
+    }
+    if digits == 0 {
+        return None;
+    }
+
+    if end < bytes.len() && matches!(bytes[end], b'e' | b'E') {
+        let mut exponent_end = end + 1;
+        if matches!(bytes.get(exponent_end), Some(b'+' | b'-')) {
+            exponent_end += 1;
+        }
+        let exponent_digits = exponent_end;
+        while exponent_end < bytes.len() && bytes[exponent_end].is_ascii_digit() {
+            exponent_end += 1;
+        }
+        if exponent_end > exponent_digits {
+            end = exponent_end;
+        }
+    }
+
+    s[..end].parse().ok()
+}
@@ -0,0 +1,624 @@
+//! A CPU interpreter for a preset's per-frame equations.
+//!
+//! [`Evaluator`] runs `per_frame_init` once and `per_frame` on every call
+//! to [`Evaluator::run_frame`], following MilkDrop's rules for which
+//! variables survive from one frame to the next. Nothing is rendered; the
+//! point is to inspect the values the equations produce.
+
+use std::collections::HashMap;
+
+use rand::{Rng, RngCore};
+
+use crate::error::Result;
+use crate::preset::expr::{AssignOp, BinaryOp, Expr, ExprKind, Program, UnaryOp};
+use crate::preset::Preset;
+
+/// Read-only variables projectM sets before running per-frame code.
+pub const INPUT_VARIABLES: &[&str] = &[
+    "time", "fps", "frame", "progress", "bass", "mid", "treb", "bass_att", "mid_att", "treb_att",
+    "meshx", "meshy", "pixelsx", "pixelsy", "aspectx", "aspecty",
+];
+
+/// Per-frame variables and their default values. Each frame starts from
+/// the preset's value for the variable, or this default if it sets none.
+pub const FRAME_VARIABLES: &[(&str, f64)] = &[
+    ("zoom", 1.0),
+    ("zoomexp", 1.0),
+    ("rot", 0.0),
+    ("warp", 1.0),
+    ("cx", 0.5),
+    ("cy", 0.5),
+    ("dx", 0.0),
+    ("dy", 0.0),
+    ("sx", 1.0),
+    ("sy", 1.0),
+    ("decay", 0.98),
+    ("gamma", 2.0),
+    ("echo_zoom", 2.0),
+    ("echo_alpha", 0.0),
+    ("echo_orient", 0.0),
+    ("wave_mode", 0.0),
+    ("wave_x", 0.5),
+    ("wave_y", 0.5),
+    ("wave_r", 1.0),
+    ("wave_g", 1.0),
+    ("wave_b", 1.0),
+    ("wave_a", 0.8),
+    ("wave_mystery", 0.0),
+    ("wave_usedots", 0.0),
+    ("wave_thick", 0.0),
+    ("wave_additive", 0.0),
+    ("wave_brighten", 1.0),
+    ("darken_center", 0.0),
+    ("brighten", 0.0),
+    ("darken", 0.0),
+    ("solarize", 0.0),
+    ("invert", 0.0),
+    ("ob_size", 0.01),
+    ("ob_r", 0.0),
+    ("ob_g", 0.0),
+    ("ob_b", 0.0),
+    ("ob_a", 0.0),
+    ("ib_size", 0.01),
+    ("ib_r", 0.25),
+    ("ib_g", 0.25),
+    ("ib_b", 0.25),
+    ("ib_a", 0.0),
+    ("mv_x", 12.0),
+    ("mv_y", 9.0),
+    ("mv_dx", 0.0),
+    ("mv_dy", 0.0),
+    ("mv_l", 0.9),
+    ("mv_r", 1.0),
+    ("mv_g", 1.0),
+    ("mv_b", 1.0),
+    ("mv_a", 1.0),
+    ("b1n", 0.0),
+    ("b2n", 0.0),
+    ("b3n", 0.0),
+    ("b1x", 1.0),
+    ("b2x", 1.0),
+    ("b3x", 1.0),
+    ("b1ed", 0.25),
+    ("monitor", 0.0),
+];
+
+/// Preset keys whose per-frame variable has a different name.
+pub const PARAMETER_VARIABLES: &[(&str, &str)] = &[
+    ("fdecay", "decay"),
+    ("fgammaadj", "gamma"),
+    ("fvideoechozoom", "echo_zoom"),
+    ("fvideoechoalpha", "echo_alpha"),
+    ("nvideoechoorientation", "echo_orient"),
+    ("nwavemode", "wave_mode"),
+    ("fwavealpha", "wave_a"),
+    ("fwaveparam", "wave_mystery"),
+    ("bwavedots", "wave_usedots"),
+    ("bwavethick", "wave_thick"),
+    ("badditivewaves", "wave_additive"),
+    ("bmaximizewavecolor", "wave_brighten"),
+    ("bdarkencenter", "darken_center"),
+    ("bbrighten", "brighten"),
+    ("bdarken", "darken"),
+    ("bsolarize", "solarize"),
+    ("binvert", "invert"),
+    ("fzoomexponent", "zoomexp"),
+    ("nmotionvectorsx", "mv_x"),
+    ("nmotionvectorsy", "mv_y"),
+];
+
+/// Number of `q` variables passed from per-frame code to the rest of the
+/// preset.
+pub const Q_VARIABLES: usize = 32;
+
+/// Size of `megabuf` and `gmegabuf`; accesses outside it read 0.
+const MEGABUF_SIZE: usize = 8 * 1024 * 1024;
+
+/// Upper bound on iterations of `loop` and `while`, as in ns-eel.
+const MAX_LOOP_ITERATIONS: usize = 1024 * 1024;
+
+/// Values closer together than this compare as equal, as in ns-eel.
+const EPSILON: f64 = 0.00001;
+
+/// The frame's inputs to per-frame code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInput {
+    pub time: f64,
+    pub fps: f64,
+    pub frame: u64,
+    /// Position within the current preset's display time, from 0 to 1.
+    pub progress: f64,
+    pub bass: f64,
+    pub mid: f64,
+    pub treb: f64,
+    pub bass_att: f64,
+    pub mid_att: f64,
+    pub treb_att: f64,
+}
+
+impl FrameInput {
+    /// Input for frame `frame` at a steady `fps`, with every audio level
+    /// at 1, which is what projectM reports for music of average loudness.
+    pub fn at_frame(frame: u64, fps: f64) -> Self {
+        FrameInput {
+            time: frame as f64 / fps,
+            fps,
+            frame,
+            progress: 0.0,
+            bass: 1.0,
+            mid: 1.0,
+            treb: 1.0,
+            bass_att: 1.0,
+            mid_att: 1.0,
+            treb_att: 1.0,
+        }
+    }
+}
+
+impl Default for FrameInput {
+    fn default() -> Self {
+        FrameInput::at_frame(0, 60.0)
+    }
+}
+
+/// Runs a preset's `per_frame_init` and `per_frame` code.
+///
+/// Before each frame the per-frame variables are reset to the preset's
+/// values and `q1`..`q32` to what `per_frame_init` left in them. Any other
+/// variable the code creates keeps its value between frames.
+pub struct Evaluator {
+    init: Program,
+    per_frame: Program,
+    /// Per-frame variables as the preset sets them.
+    base: Vec<(String, f64)>,
+    /// `q1`..`q32` after `per_frame_init` ran.
+    q_init: Option<[f64; Q_VARIABLES]>,
+    variables: HashMap<String, f64>,
+    megabuf: HashMap<usize, f64>,
+    gmegabuf: HashMap<usize, f64>,
+    rng: Box<dyn RngCore>,
+    frames: u64,
+}
+
+impl Evaluator {
+    /// Parse the preset's per-frame code. `rand()` uses the thread's
+    /// random number generator.
+    pub fn new(preset: &Preset) -> Result<Evaluator> {
+        Self::with_rng(preset, rand::thread_rng())
+    }
+
+    /// Like [`Evaluator::new`], but `rand()` draws from `rng`, so runs
+    /// can be reproduced.
+    pub fn with_rng<R: RngCore + 'static>(preset: &Preset, rng: R) -> Result<Evaluator> {
+        let init = Program::parse_equations(&preset.per_frame_init)?;
+        let per_frame = Program::parse_equations(&preset.per_frame)?;
+
+        let mut base: Vec<(String, f64)> = FRAME_VARIABLES
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect();
+        // Walk backwards so the first occurrence of a key wins, as in
+        // `Preset::get`.
+        for parameter in preset.parameters.iter().rev() {
+            let Some((name, _)) = frame_variable(&parameter.key) else {
+                continue;
+            };
+            let slot = base.iter_mut().find(|(variable, _)| variable == name);
+            if let (Some(slot), Some(value)) = (slot, parameter.as_f64()) {
+                slot.1 = value;
+            }
+        }
+
+        Ok(Evaluator {
+            init,
+            per_frame,
+            base,
+            q_init: None,
+            variables: HashMap::new(),
+            megabuf: HashMap::new(),
+            gmegabuf: HashMap::new(),
+            rng: Box::new(rng),
+            frames: 0,
+        })
+    }
+
+    /// Run one frame. The first call also runs `per_frame_init`.
+    pub fn run_frame(&mut self, input: &FrameInput) {
+        self.set_inputs(input);
+        for (name, value) in &self.base {
+            self.variables.insert(name.clone(), *value);
+        }
+
+        let q_init = match self.q_init {
+            Some(q_init) => q_init,
+            None => {
+                let init = std::mem::take(&mut self.init);
+                self.execute(&init);
+                self.init = init;
+
+                let q_init = std::array::from_fn(|i| self.q(i + 1));
+                self.q_init = Some(q_init);
+                q_init
+            }
+        };
+        for (i, value) in q_init.iter().enumerate() {
+            self.variables.insert(format!("q{}", i + 1), *value);
+        }
+
+        let per_frame = std::mem::take(&mut self.per_frame);
+        self.execute(&per_frame);
+        self.per_frame = per_frame;
+        self.frames += 1;
+    }
+
+    /// Run `count` frames at a steady `fps`, continuing from the frames
+    /// already run.
+    pub fn run_frames(&mut self, count: u64, fps: f64) {
+        for _ in 0..count {
+            self.run_frame(&FrameInput::at_frame(self.frames, fps));
+        }
+    }
+
+    /// Number of frames run so far.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The current value of a variable, ignoring case. Variables that were
+    /// never set read as 0, as they do in MilkDrop.
+    pub fn get(&self, name: &str) -> f64 {
+        let value = match self.variables.get(name) {
+            Some(value) => Some(value),
+            None => self.variables.get(&name.to_ascii_lowercase()),
+        };
+        value.copied().unwrap_or(0.0)
+    }
+
+    /// Set a variable, e.g. to feed a value to the next frame.
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.variables.insert(name.to_ascii_lowercase(), value);
+    }
+
+    /// The value of `q1`..`q32`.
+    pub fn q(&self, index: usize) -> f64 {
+        self.get(&format!("q{index}"))
+    }
+
+    /// Every variable that has been set, sorted by name.
+    pub fn variables(&self) -> Vec<(&str, f64)> {
+        let mut variables: Vec<(&str, f64)> = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    fn set_inputs(&mut self, input: &FrameInput) {
+        let inputs = [
+            ("time", input.time),
+            ("fps", input.fps),
+            ("frame", input.frame as f64),
+            ("progress", input.progress),
+            ("bass", input.bass),
+            ("mid", input.mid),
+            ("treb", input.treb),
+            ("bass_att", input.bass_att),
+            ("mid_att", input.mid_att),
+            ("treb_att", input.treb_att),
+            ("meshx", 48.0),
+            ("meshy", 36.0),
+            ("pixelsx", 1024.0),
+            ("pixelsy", 768.0),
+            ("aspectx", 1.0),
+            ("aspecty", 1.0),
+        ];
+        for (name, value) in inputs {
+            self.variables.insert(name.to_string(), value);
+        }
+    }
+
+    fn execute(&mut self, program: &Program) {
+        for statement in &program.statements {
+            self.eval(statement);
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> f64 {
+        match &expr.kind {
+            ExprKind::Number(value) => *value,
+            ExprKind::Variable(name) => self.get(name),
+            ExprKind::Assign { op, target, value } => {
+                let value = self.eval(value);
+                self.assign(target, *op, value)
+            }
+            ExprKind::Unary { op, operand } => {
+                let operand = self.eval(operand);
+                match op {
+                    UnaryOp::Neg => -operand,
+                    UnaryOp::Plus => operand,
+                    UnaryOp::Not => bool_value(!is_true(operand)),
+                }
+            }
+            ExprKind::Binary {
+                op: BinaryOp::And,
+                left,
+                right,
+            } => bool_value(is_true(self.eval(left)) && is_true(self.eval(right))),
+            ExprKind::Binary {
+                op: BinaryOp::Or,
+                left,
+                right,
+            } => bool_value(is_true(self.eval(left)) || is_true(self.eval(right))),
+            ExprKind::Binary { op, left, right } => {
+                let left = self.eval(left);
+                let right = self.eval(right);
+                binary(*op, left, right)
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if is_true(self.eval(condition)) {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            ExprKind::Call { function, args } => self.call(function, args),
+        }
+    }
+
+    fn assign(&mut self, target: &Expr, op: AssignOp, value: f64) -> f64 {
+        let (name, megabuf) = match &target.kind {
+            ExprKind::Variable(name) => (Some(name), None),
+            ExprKind::Call { function, args } => {
+                let global = function.eq_ignore_ascii_case("gmegabuf");
+                let index = self.eval(&args[0]);
+                (None, Some((global, megabuf_index(index))))
+            }
+            _ => return value,
+        };
+
+        let current = match (name, megabuf) {
+            (Some(name), _) => self.get(name),
+            (None, Some((global, Some(index)))) => *self.buffer(global).get(&index).unwrap_or(&0.0),
+            _ => 0.0,
+        };
+        let value = match op {
+            AssignOp::Assign => value,
+            AssignOp::Add => current + value,
+            AssignOp::Sub => current - value,
+            AssignOp::Mul => current * value,
+            AssignOp::Div => binary(BinaryOp::Div, current, value),
+            AssignOp::Rem => binary(BinaryOp::Rem, current, value),
+        };
+
+        match (name, megabuf) {
+            (Some(name), _) => self.set(name, value),
+            (None, Some((global, Some(index)))) => {
+                self.buffer(global).insert(index, value);
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn buffer(&mut self, global: bool) -> &mut HashMap<usize, f64> {
+        if global {
+            &mut self.gmegabuf
+        } else {
+            &mut self.megabuf
+        }
+    }
+
+    fn call(&mut self, function: &str, args: &[Expr]) -> f64 {
+        let function = function.to_ascii_lowercase();
+
+        // Functions that don't evaluate all of their arguments up front.
+        match function.as_str() {
+            "if" => {
+                return if is_true(self.eval(&args[0])) {
+                    self.eval(&args[1])
+                } else {
+                    self.eval(&args[2])
+                };
+            }
+            "band" => {
+                return bool_value(is_true(self.eval(&args[0])) && is_true(self.eval(&args[1])))
+            }
+            "bor" => {
+                return bool_value(is_true(self.eval(&args[0])) || is_true(self.eval(&args[1])))
+            }
+            "loop" => {
+                let count = self.eval(&args[0]);
+                let count = if count > 0.0 { count as usize } else { 0 };
+                let mut value = 0.0;
+                for _ in 0..count.min(MAX_LOOP_ITERATIONS) {
+                    value = self.eval(&args[1]);
+                }
+                return value;
+            }
+            "while" => {
+                for _ in 0..MAX_LOOP_ITERATIONS {
+                    if !is_true(self.eval(&args[0])) {
+                        break;
+                    }
+                }
+                return 0.0;
+            }
+            _ => {}
+        }
+
+        let values: Vec<f64> = args.iter().map(|arg| self.eval(arg)).collect();
+        let x = values.first().copied().unwrap_or(0.0);
+        let y = values.get(1).copied().unwrap_or(0.0);
+
+        match function.as_str() {
+            "sin" => x.sin(),
+            "cos" => x.cos(),
+            "tan" => x.tan(),
+            "asin" => x.asin(),
+            "acos" => x.acos(),
+            "atan" => x.atan(),
+            "atan2" => x.atan2(y),
+            "sqr" => x * x,
+            "sqrt" => x.abs().sqrt(),
+            "pow" => x.powf(y),
+            "exp" => x.exp(),
+            "log" => x.ln(),
+            "log10" => x.log10(),
+            "abs" => x.abs(),
+            "min" => x.min(y),
+            "max" => x.max(y),
+            "sign" => {
+                if x > 0.0 {
+                    1.0
+                } else if x < 0.0 {
+                    -1.0
+                } else {
+                    0.0
+                }
+            }
+            "rand" => {
+                let range = x.floor().max(1.0) as u64;
+                self.rng.gen_range(0..range) as f64
+            }
+            "int" => x.trunc(),
+            "floor" => x.floor(),
+            "ceil" => x.ceil(),
+            "invsqrt" => 1.0 / x.abs().sqrt(),
+            "sigmoid" => 1.0 / (1.0 + (-x * y).exp()),
+            "bnot" => bool_value(!is_true(x)),
+            "equal" => bool_value((x - y).abs() < EPSILON),
+            "above" => bool_value(x > y),
+            "below" => bool_value(x < y),
+            "megabuf" => megabuf_index(x).map_or(0.0, |i| *self.megabuf.get(&i).unwrap_or(&0.0)),
+            "gmegabuf" => megabuf_index(x).map_or(0.0, |i| *self.gmegabuf.get(&i).unwrap_or(&0.0)),
+            "freembuf" => {
+                // Frees everything from index `x` on.
+                if let Some(start) = megabuf_index(x) {
+                    self.megabuf.retain(|&index, _| index < start);
+                }
+                x
+            }
+            "memcpy" => {
+                self.memcpy(x, y, values[2]);
+                x
+            }
+            "memset" => {
+                self.memset(x, y, values[2]);
+                x
+            }
+            "exec2" | "exec3" => values.last().copied().unwrap_or(0.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Copy `len` elements of `megabuf` from `src` to `dest`. Overlapping
+    /// ranges are copied as if through a temporary buffer.
+    fn memcpy(&mut self, dest: f64, src: f64, len: f64) {
+        let (Some(dest), Some(src)) = (megabuf_index(dest), megabuf_index(src)) else {
+            return;
+        };
+        let len = megabuf_len(len)
+            .min(MEGABUF_SIZE - dest)
+            .min(MEGABUF_SIZE - src);
+
+        let copied: Vec<(usize, f64)> = self
+            .megabuf
+            .iter()
+            .filter(|(&index, _)| (src..src + len).contains(&index))
+            .map(|(&index, &value)| (index - src + dest, value))
+            .collect();
+        self.megabuf
+            .retain(|index, _| !(dest..dest + len).contains(index));
+        self.megabuf.extend(copied);
+    }
+
+    /// Set `len` elements of `megabuf` from `dest` on to `value`.
+    fn memset(&mut self, dest: f64, value: f64, len: f64) {
+        let Some(dest) = megabuf_index(dest) else {
+            return;
+        };
+        let range = dest..dest + megabuf_len(len).min(MEGABUF_SIZE - dest);
+
+        // Unset elements read 0, so there is nothing to store for 0.
+        self.megabuf.retain(|index, _| !range.contains(index));
+        if value != 0.0 {
+            self.megabuf.extend(range.map(|index| (index, value)));
+        }
+    }
+}
+
+/// The per-frame variable a preset key sets, with its default value.
+pub(crate) fn frame_variable(key: &str) -> Option<(&'static str, f64)> {
+    let key = key.to_ascii_lowercase();
+    let name = PARAMETER_VARIABLES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key.as_str(), |&(_, name)| name);
+
+    FRAME_VARIABLES
+        .iter()
+        .find(|(variable, _)| *variable == name)
+        .copied()
+}
+
+fn binary(op: BinaryOp, left: f64, right: f64) -> f64 {
+    match op {
+        BinaryOp::Add => left + right,
+        BinaryOp::Sub => left - right,
+        BinaryOp::Mul => left * right,
+        // Division by zero yields 0 rather than infinity, as in projectM.
+        BinaryOp::Div if right == 0.0 => 0.0,
+        BinaryOp::Div => left / right,
+        BinaryOp::Rem => {
+            let divisor = right as i64;
+            if divisor == 0 {
+                0.0
+            } else {
+                ((left as i64) % divisor) as f64
+            }
+        }
+        BinaryOp::Pow => left.powf(right),
+        BinaryOp::BitOr => ((left as i64) | (right as i64)) as f64,
+        BinaryOp::BitAnd => ((left as i64) & (right as i64)) as f64,
+        BinaryOp::Or => bool_value(is_true(left) || is_true(right)),
+        BinaryOp::And => bool_value(is_true(left) && is_true(right)),
+        BinaryOp::Eq => bool_value((left - right).abs() < EPSILON),
+        BinaryOp::Ne => bool_value((left - right).abs() >= EPSILON),
+        BinaryOp::Lt => bool_value(left < right),
+        BinaryOp::Gt => bool_value(left > right),
+        BinaryOp::Le => bool_value(left <= right),
+        BinaryOp::Ge => bool_value(left >= right),
+    }
+}
+
+fn is_true(value: f64) -> bool {
+    value.abs() >= EPSILON
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn megabuf_len(len: f64) -> usize {
+    let len = (len + EPSILON).floor();
+    if len > 0.0 {
+        len.min(MEGABUF_SIZE as f64) as usize
+    } else {
+        0
+    }
+}
+
+fn megabuf_index(index: f64) -> Option<usize> {
+    let index = (index + EPSILON).floor();
+    (index >= 0.0 && index < MEGABUF_SIZE as f64).then_some(index as usize)
+}
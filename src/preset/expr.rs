@@ -0,0 +1,953 @@
+//! The MilkDrop expression language used by preset equations.
+//!
+//! Code such as `ib_r=0.7+0.4*sin(3*time);` parses into a [`Program`] of
+//! statements. Each [`Expr`] carries the [`Span`] it was parsed from, and
+//! the [`fmt::Display`] impls print the AST back in canonical form.
+
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::preset::{Equation, Span};
+
+/// Built-in functions and the number of arguments they take.
+///
+/// The names starting with `_`, and `assign`, are ns-eel's function forms
+/// of the operators; they parse into the same expressions as the operators
+/// they stand for.
+pub const FUNCTIONS: &[(&str, usize)] = &[
+    ("sin", 1),
+    ("cos", 1),
+    ("tan", 1),
+    ("asin", 1),
+    ("acos", 1),
+    ("atan", 1),
+    ("atan2", 2),
+    ("sqr", 1),
+    ("sqrt", 1),
+    ("pow", 2),
+    ("exp", 1),
+    ("log", 1),
+    ("log10", 1),
+    ("abs", 1),
+    ("min", 2),
+    ("max", 2),
+    ("sign", 1),
+    ("rand", 1),
+    ("int", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("invsqrt", 1),
+    ("sigmoid", 2),
+    ("bnot", 1),
+    ("band", 2),
+    ("bor", 2),
+    ("if", 3),
+    ("equal", 2),
+    ("above", 2),
+    ("below", 2),
+    ("megabuf", 1),
+    ("gmegabuf", 1),
+    ("freembuf", 1),
+    ("memcpy", 3),
+    ("memset", 3),
+    ("exec2", 2),
+    ("exec3", 3),
+    ("loop", 2),
+    ("while", 1),
+    ("assign", 2),
+    ("_if", 3),
+    ("_and", 2),
+    ("_or", 2),
+    ("_not", 1),
+    ("_neg", 1),
+    ("_equal", 2),
+    ("_noteq", 2),
+    ("_below", 2),
+    ("_above", 2),
+    ("_beleq", 2),
+    ("_aboeq", 2),
+    ("_add", 2),
+    ("_sub", 2),
+    ("_mul", 2),
+    ("_div", 2),
+    ("_mod", 2),
+    ("_set", 2),
+    ("_addop", 2),
+    ("_subop", 2),
+    ("_mulop", 2),
+    ("_divop", 2),
+    ("_modop", 2),
+    ("_orop", 2),
+    ("_andop", 2),
+    ("_powop", 2),
+    ("_mem", 1),
+    ("_gmem", 1),
+];
+
+/// The number of arguments a built-in function takes, ignoring case.
+pub fn function_arity(name: &str) -> Option<usize> {
+    FUNCTIONS
+        .iter()
+        .find(|(function, _)| function.eq_ignore_ascii_case(name))
+        .map(|&(_, arity)| arity)
+}
+
+/// A sequence of `;`-separated statements.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Expr>,
+}
+
+/// An expression together with the source it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(f64),
+    Variable(String),
+    /// `target op value`, where the target is a variable or a
+    /// `megabuf`/`gmegabuf` element.
+    Assign {
+        op: AssignOp,
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `condition ? then : otherwise`
+    Conditional {
+        condition: Box<Expr>,
+        then: Box<Expr>,
+        otherwise: Box<Expr>,
+    },
+    Call {
+        function: String,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssignOp {
+    Assign,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    BitOr,
+    BitAnd,
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl AssignOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AssignOp::Assign => "=",
+            AssignOp::Add => "+=",
+            AssignOp::Sub => "-=",
+            AssignOp::Mul => "*=",
+            AssignOp::Div => "/=",
+            AssignOp::Rem => "%=",
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Pow => "^",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::Or => "||",
+            BinaryOp::And => "&&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => OR,
+            BinaryOp::And => AND,
+            BinaryOp::BitOr => BIT_OR,
+            BinaryOp::BitAnd => BIT_AND,
+            BinaryOp::Eq
+            | BinaryOp::Ne
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Le
+            | BinaryOp::Ge => COMPARE,
+            BinaryOp::Add | BinaryOp::Sub => ADDITIVE,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => MULTIPLICATIVE,
+            BinaryOp::Pow => POWER,
+        }
+    }
+}
+
+// Binding strength of each level of the grammar, loosest first.
+const ASSIGN: u8 = 1;
+const CONDITIONAL: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const BIT_OR: u8 = 5;
+const BIT_AND: u8 = 6;
+const COMPARE: u8 = 7;
+const ADDITIVE: u8 = 8;
+const MULTIPLICATIVE: u8 = 9;
+const UNARY: u8 = 10;
+const POWER: u8 = 11;
+const PRIMARY: u8 = 12;
+
+impl Program {
+    /// Parse a block of code. Spans are byte offsets into `code`.
+    pub fn parse(code: &str) -> Result<Program> {
+        let tokens = tokenize(code)?;
+        Parser {
+            tokens,
+            position: 0,
+            end: code.len(),
+            depth: 0,
+        }
+        .program()
+    }
+
+    /// Parse numbered equation lines as one block, the way MilkDrop joins
+    /// them, so an expression may continue from one line onto the next.
+    /// Only the [loaded](Equation::loaded) lines are parsed.
+    ///
+    /// Spans are mapped back to the preset source the equations were
+    /// parsed from.
+    pub fn parse_equations(equations: &[Equation]) -> Result<Program> {
+        let sorted = Equation::loaded(equations);
+
+        let mut code = String::new();
+        let mut segments = Vec::with_capacity(sorted.len());
+        for equation in sorted {
+            if !code.is_empty() {
+                code.push('\n');
+            }
+            segments.push((code.len(), equation.code_span.start, equation.code.len()));
+            code.push_str(&equation.code);
+        }
+
+        let map = |position: usize| {
+            let segment = segments
+                .iter()
+                .rev()
+                .find(|(start, _, _)| *start <= position);
+            match segment {
+                Some(&(start, source_start, len)) => source_start + (position - start).min(len),
+                None => position,
+            }
+        };
+        let map_span = |span: Span| Span::new(map(span.start), map(span.end));
+
+        match Program::parse(&code) {
+            Ok(mut program) => {
+                for statement in &mut program.statements {
+                    statement.map_spans(&map_span);
+                }
+                Ok(program)
+            }
+            Err(Error::ExpressionSyntax { span, message }) => Err(Error::ExpressionSyntax {
+                span: map_span(span),
+                message,
+            }),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
+    /// Call `f` on this expression and every expression inside it,
+    /// parents before children.
+    pub fn visit(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        match &self.kind {
+            ExprKind::Number(_) | ExprKind::Variable(_) => {}
+            ExprKind::Assign { target, value, .. } => {
+                target.visit(f);
+                value.visit(f);
+            }
+            ExprKind::Unary { operand, .. } => operand.visit(f),
+            ExprKind::Binary { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.visit(f);
+                then.visit(f);
+                otherwise.visit(f);
+            }
+            ExprKind::Call { args, .. } => args.iter().for_each(|arg| arg.visit(f)),
+        }
+    }
+
+    fn map_spans(&mut self, map: &impl Fn(Span) -> Span) {
+        self.span = map(self.span);
+        match &mut self.kind {
+            ExprKind::Number(_) | ExprKind::Variable(_) => {}
+            ExprKind::Assign { target, value, .. } => {
+                target.map_spans(map);
+                value.map_spans(map);
+            }
+            ExprKind::Unary { operand, .. } => operand.map_spans(map),
+            ExprKind::Binary { left, right, .. } => {
+                left.map_spans(map);
+                right.map_spans(map);
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.map_spans(map);
+                then.map_spans(map);
+                otherwise.map_spans(map);
+            }
+            ExprKind::Call { args, .. } => args.iter_mut().for_each(|arg| arg.map_spans(map)),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match &self.kind {
+            ExprKind::Number(value) if *value < 0.0 => UNARY,
+            ExprKind::Number(_) | ExprKind::Variable(_) | ExprKind::Call { .. } => PRIMARY,
+            ExprKind::Assign { .. } => ASSIGN,
+            ExprKind::Unary { .. } => UNARY,
+            ExprKind::Binary { op, .. } => op.precedence(),
+            ExprKind::Conditional { .. } => CONDITIONAL,
+        }
+    }
+
+    /// Print the expression, parenthesized unless it binds at least as
+    /// tightly as `precedence`.
+    fn fmt_at(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    /// Print one statement per line, each terminated by `;`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, statement) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{statement};")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Number(value) => write!(f, "{value}"),
+            ExprKind::Variable(name) => write!(f, "{name}"),
+            ExprKind::Assign { op, target, value } => {
+                write!(f, "{target} {} ", op.as_str())?;
+                value.fmt_at(f, ASSIGN)
+            }
+            ExprKind::Unary { op, operand } => {
+                write!(f, "{}", op.as_str())?;
+                operand.fmt_at(f, UNARY)
+            }
+            ExprKind::Binary {
+                op: BinaryOp::Pow,
+                left,
+                right,
+            } => {
+                left.fmt_at(f, PRIMARY)?;
+                write!(f, " ^ ")?;
+                right.fmt_at(f, UNARY)
+            }
+            ExprKind::Binary { op, left, right } => {
+                let precedence = op.precedence();
+                left.fmt_at(f, precedence)?;
+                write!(f, " {} ", op.as_str())?;
+                right.fmt_at(f, precedence + 1)
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.fmt_at(f, OR)?;
+                write!(f, " ? ")?;
+                then.fmt_at(f, ASSIGN)?;
+                write!(f, " : ")?;
+                otherwise.fmt_at(f, CONDITIONAL)
+            }
+            ExprKind::Call { function, args } => {
+                write!(f, "{function}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(&'static str),
+}
+
+/// Symbols of the language, longest first so `<=` wins over `<`.
+const SYMBOLS: &[&str] = &[
+    "+=", "-=", "*=", "/=", "%=", "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "^",
+    "=", "<", ">", "!", "&", "|", "?", ":", "(", ")", ",", ";",
+];
+
+fn tokenize(code: &str) -> Result<Vec<(Token, Span)>> {
+    let bytes = code.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &code[i..];
+        let c = bytes[i];
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            let Some(end) = comment.find("*/") else {
+                return Err(syntax_error(i, code.len(), "unterminated `/*` comment"));
+            };
+            i += end + 4;
+        } else if c.is_ascii_digit()
+            || (c == b'.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let len = number_len(rest);
+            let value = rest[..len]
+                .parse()
+                .map_err(|_| syntax_error(i, i + len, "invalid number"))?;
+            tokens.push((Token::Number(value), Span::new(i, i + len)));
+            i += len;
+        } else if c == b'$' {
+            let len = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len() - 1);
+            let value = match rest[1..len].to_ascii_lowercase().as_str() {
+                "pi" => std::f64::consts::PI,
+                "e" => std::f64::consts::E,
+                "phi" => 1.618_033_988_749_895,
+                _ => return Err(syntax_error(i, i + len, "unknown constant")),
+            };
+            tokens.push((Token::Number(value), Span::new(i, i + len)));
+            i += len;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((Token::Ident(rest[..len].to_string()), Span::new(i, i + len)));
+            i += len;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push((Token::Symbol(symbol), Span::new(i, i + symbol.len())));
+            i += symbol.len();
+        } else {
+            let len = rest.chars().next().map_or(1, char::len_utf8);
+            let message = format!("unexpected character `{}`", &rest[..len]);
+            return Err(syntax_error(i, i + len, message));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The length of the number at the start of `s`: digits, an optional
+/// fraction and an optional exponent.
+fn number_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = digits(0);
+    if bytes.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        let exponent_end = digits(end + 1 + sign);
+        if exponent_end > end + 1 + sign {
+            end = exponent_end;
+        }
+    }
+    end
+}
+
+fn syntax_error(start: usize, end: usize, message: impl Into<String>) -> Error {
+    Error::ExpressionSyntax {
+        span: Span::new(start, end),
+        message: message.into(),
+    }
+}
+
+/// How deeply expressions may nest before parsing gives up, so hostile
+/// input cannot overflow the stack of the parser, printer or evaluator.
+const MAX_DEPTH: usize = 64;
+
+fn check_assignable(target: &Expr) -> Result<()> {
+    let assignable = match &target.kind {
+        ExprKind::Variable(_) => true,
+        ExprKind::Call { function, .. } => {
+            function.eq_ignore_ascii_case("megabuf") || function.eq_ignore_ascii_case("gmegabuf")
+        }
+        _ => false,
+    };
+    if !assignable {
+        return Err(syntax_error(
+            target.span.start,
+            target.span.end,
+            "can only assign to a variable or a megabuf element",
+        ));
+    }
+    Ok(())
+}
+
+/// A call with its arity already checked, with ns-eel's function forms of
+/// the operators turned into the operators themselves.
+fn operator_call(function: String, mut args: Vec<Expr>, span: Span) -> Result<Expr> {
+    let name = function.to_ascii_lowercase();
+
+    let renamed = match name.as_str() {
+        "_if" => Some("if"),
+        "_mem" => Some("megabuf"),
+        "_gmem" => Some("gmegabuf"),
+        _ => None,
+    };
+    if let Some(function) = renamed {
+        let function = function.to_string();
+        return Ok(Expr::new(ExprKind::Call { function, args }, span));
+    }
+
+    let unary = match name.as_str() {
+        "_not" => Some(UnaryOp::Not),
+        "_neg" => Some(UnaryOp::Neg),
+        _ => None,
+    };
+    if let Some(op) = unary {
+        let operand = Box::new(args.remove(0));
+        return Ok(Expr::new(ExprKind::Unary { op, operand }, span));
+    }
+
+    let binary = match name.as_str() {
+        "_and" => Some(BinaryOp::And),
+        "_or" => Some(BinaryOp::Or),
+        "_equal" => Some(BinaryOp::Eq),
+        "_noteq" => Some(BinaryOp::Ne),
+        "_below" => Some(BinaryOp::Lt),
+        "_above" => Some(BinaryOp::Gt),
+        "_beleq" => Some(BinaryOp::Le),
+        "_aboeq" => Some(BinaryOp::Ge),
+        "_add" => Some(BinaryOp::Add),
+        "_sub" => Some(BinaryOp::Sub),
+        "_mul" => Some(BinaryOp::Mul),
+        "_div" => Some(BinaryOp::Div),
+        "_mod" => Some(BinaryOp::Rem),
+        _ => None,
+    };
+    if let Some(op) = binary {
+        let right = Box::new(args.remove(1));
+        let left = Box::new(args.remove(0));
+        return Ok(Expr::new(ExprKind::Binary { op, left, right }, span));
+    }
+
+    // Compound assignments without an operator of their own assign the
+    // result of the binary operation.
+    let assign = match name.as_str() {
+        "assign" | "_set" => Some((AssignOp::Assign, None)),
+        "_addop" => Some((AssignOp::Add, None)),
+        "_subop" => Some((AssignOp::Sub, None)),
+        "_mulop" => Some((AssignOp::Mul, None)),
+        "_divop" => Some((AssignOp::Div, None)),
+        "_modop" => Some((AssignOp::Rem, None)),
+        "_orop" => Some((AssignOp::Assign, Some(BinaryOp::BitOr))),
+        "_andop" => Some((AssignOp::Assign, Some(BinaryOp::BitAnd))),
+        "_powop" => Some((AssignOp::Assign, Some(BinaryOp::Pow))),
+        _ => None,
+    };
+    if let Some((op, binary)) = assign {
+        let mut value = args.remove(1);
+        let target = args.remove(0);
+        check_assignable(&target)?;
+        if let Some(op) = binary {
+            value = Expr::new(
+                ExprKind::Binary {
+                    op,
+                    left: Box::new(target.clone()),
+                    right: Box::new(value),
+                },
+                span,
+            );
+        }
+        return Ok(Expr::new(
+            ExprKind::Assign {
+                op,
+                target: Box::new(target),
+                value: Box::new(value),
+            },
+            span,
+        ));
+    }
+
+    Ok(Expr::new(ExprKind::Call { function, args }, span))
+}
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+    /// Length of the code, used for errors at the end of input.
+    end: usize,
+    /// Current nesting depth, see [`MAX_DEPTH`].
+    depth: usize,
+}
+
+impl Parser {
+    fn program(mut self) -> Result<Program> {
+        let mut statements = Vec::new();
+        while self.position < self.tokens.len() {
+            if self.eat(";").is_some() {
+                continue;
+            }
+            statements.push(self.assignment()?);
+            if self.position < self.tokens.len() && self.eat(";").is_none() {
+                return Err(self.unexpected("expected `;`"));
+            }
+        }
+        Ok(Program { statements })
+    }
+
+    fn assignment(&mut self) -> Result<Expr> {
+        let target = self.conditional()?;
+
+        let op = match self.peek_symbol() {
+            Some("=") => AssignOp::Assign,
+            Some("+=") => AssignOp::Add,
+            Some("-=") => AssignOp::Sub,
+            Some("*=") => AssignOp::Mul,
+            Some("/=") => AssignOp::Div,
+            Some("%=") => AssignOp::Rem,
+            _ => return Ok(target),
+        };
+
+        check_assignable(&target)?;
+
+        self.position += 1;
+        let value = self.nested(Self::assignment)?;
+        let span = Span::new(target.span.start, value.span.end);
+        Ok(Expr::new(
+            ExprKind::Assign {
+                op,
+                target: Box::new(target),
+                value: Box::new(value),
+            },
+            span,
+        ))
+    }
+
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.binary(OR)?;
+        if self.eat("?").is_none() {
+            return Ok(condition);
+        }
+
+        let then = self.nested(Self::assignment)?;
+        if self.eat(":").is_none() {
+            return Err(self.unexpected("expected `:`"));
+        }
+        let otherwise = self.nested(Self::conditional)?;
+
+        let span = Span::new(condition.span.start, otherwise.span.end);
+        Ok(Expr::new(
+            ExprKind::Conditional {
+                condition: Box::new(condition),
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            },
+            span,
+        ))
+    }
+
+    /// Left-associative binary operators binding at least as tightly as
+    /// `precedence`.
+    fn binary(&mut self, precedence: u8) -> Result<Expr> {
+        if precedence > MULTIPLICATIVE {
+            return self.unary();
+        }
+
+        let mut left = self.binary(precedence + 1)?;
+        while let Some(op) = self
+            .peek_binary_op()
+            .filter(|op| op.precedence() == precedence)
+        {
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+            let span = Span::new(left.span.start, right.span.end);
+            left = Expr::new(
+                ExprKind::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+                span,
+            );
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let op = match self.peek_symbol() {
+            Some("-") => UnaryOp::Neg,
+            Some("+") => UnaryOp::Plus,
+            Some("!") => UnaryOp::Not,
+            _ => return self.power(),
+        };
+
+        let start = self.tokens[self.position].1.start;
+        self.position += 1;
+        let operand = self.nested(Self::unary)?;
+        let span = Span::new(start, operand.span.end);
+        Ok(Expr::new(
+            ExprKind::Unary {
+                op,
+                operand: Box::new(operand),
+            },
+            span,
+        ))
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.primary()?;
+        if self.eat("^").is_none() {
+            return Ok(base);
+        }
+
+        let exponent = self.nested(Self::unary)?;
+        let span = Span::new(base.span.start, exponent.span.end);
+        Ok(Expr::new(
+            ExprKind::Binary {
+                op: BinaryOp::Pow,
+                left: Box::new(base),
+                right: Box::new(exponent),
+            },
+            span,
+        ))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let Some((token, span)) = self.tokens.get(self.position).cloned() else {
+            return Err(self.unexpected("expected an expression"));
+        };
+
+        match token {
+            Token::Number(value) => {
+                self.position += 1;
+                Ok(Expr::new(ExprKind::Number(value), span))
+            }
+            Token::Ident(name) => {
+                self.position += 1;
+                if self.eat("(").is_none() {
+                    return Ok(Expr::new(ExprKind::Variable(name), span));
+                }
+                self.call(name, span)
+            }
+            Token::Symbol("(") => {
+                self.position += 1;
+                let mut inner = self.nested(Self::assignment)?;
+                let Some(close) = self.eat(")") else {
+                    return Err(self.unexpected("expected `)`"));
+                };
+                inner.span = Span::new(span.start, close.end);
+                Ok(inner)
+            }
+            Token::Symbol(_) => Err(self.unexpected("expected an expression")),
+        }
+    }
+
+    /// Arguments of a call to `function`, after its opening parenthesis.
+    fn call(&mut self, function: String, name_span: Span) -> Result<Expr> {
+        let mut args = Vec::new();
+        let close = match self.eat(")") {
+            Some(close) => close,
+            None => loop {
+                args.push(self.nested(Self::assignment)?);
+                if let Some(close) = self.eat(")") {
+                    break close;
+                }
+                if self.eat(",").is_none() {
+                    return Err(self.unexpected("expected `,` or `)`"));
+                }
+            },
+        };
+
+        let span = Span::new(name_span.start, close.end);
+        let Some(arity) = function_arity(&function) else {
+            let message = format!("unknown function `{function}`");
+            return Err(syntax_error(name_span.start, name_span.end, message));
+        };
+        if args.len() != arity {
+            let plural = if arity == 1 { "" } else { "s" };
+            let message = format!(
+                "`{function}` takes {arity} argument{plural} but {} were given",
+                args.len()
+            );
+            return Err(syntax_error(span.start, span.end, message));
+        }
+
+        operator_call(function, args, span)
+    }
+
+    /// Run `parse` one nesting level deeper, failing past [`MAX_DEPTH`].
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth == MAX_DEPTH {
+            let span = self
+                .tokens
+                .get(self.position)
+                .map_or(Span::new(self.end, self.end), |(_, span)| *span);
+            return Err(syntax_error(
+                span.start,
+                span.end,
+                "expression nested too deeply",
+            ));
+        }
+
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some((Token::Symbol(symbol), _)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn peek_binary_op(&self) -> Option<BinaryOp> {
+        let op = match self.peek_symbol()? {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "%" => BinaryOp::Rem,
+            "|" => BinaryOp::BitOr,
+            "&" => BinaryOp::BitAnd,
+            "||" => BinaryOp::Or,
+            "&&" => BinaryOp::And,
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            ">" => BinaryOp::Gt,
+            "<=" => BinaryOp::Le,
+            ">=" => BinaryOp::Ge,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Consume the next token if it is `symbol`, returning its span.
+    fn eat(&mut self, symbol: &str) -> Option<Span> {
+        match self.tokens.get(self.position) {
+            Some((Token::Symbol(found), span)) if *found == symbol => {
+                self.position += 1;
+                Some(*span)
+            }
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self, message: &str) -> Error {
+        match self.tokens.get(self.position) {
+            Some((token, span)) => {
+                let found = match token {
+                    Token::Number(value) => value.to_string(),
+                    Token::Ident(name) => name.clone(),
+                    Token::Symbol(symbol) => symbol.to_string(),
+                };
+                syntax_error(span.start, span.end, format!("{message}, found `{found}`"))
+            }
+            None => syntax_error(self.end, self.end, format!("{message}, found end of code")),
+        }
+    }
+}
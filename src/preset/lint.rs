@@ -0,0 +1,785 @@
+//! Static checks for presets.
+//!
+//! [`lint`] looks for mistakes MilkDrop and projectM silently tolerate:
+//! misspelled keys, values outside their useful range, equations reading
+//! variables nothing ever sets, numbering that makes the loader skip lines,
+//! and so on. Every [`Diagnostic`] points at a [`Span`] of the source and
+//! can be rendered in the style of rustc's error messages.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::error::Error;
+use crate::preset::eval::{frame_variable, FRAME_VARIABLES, INPUT_VARIABLES, Q_VARIABLES};
+use crate::preset::expr::{Expr, ExprKind, Program};
+use crate::preset::{Equation, Parameter, Preset, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem found in a preset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Short kebab-case name of the check, e.g. `unknown-key`.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+/// A 1-based line and column in a preset's source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Location {
+    pub line: usize,
+    /// Counted in characters, not bytes.
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of byte `offset` in `source`.
+    pub fn of(source: &str, offset: usize) -> Location {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: &'static str, message: String, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            span,
+        }
+    }
+
+    fn warning(code: &'static str, message: String, span: Span) -> Self {
+        Self::new(Severity::Warning, code, message, span)
+    }
+
+    /// Where the diagnostic starts and ends in `source`.
+    pub fn location(&self, source: &str) -> (Location, Location) {
+        (
+            Location::of(source, self.span.start),
+            Location::of(source, self.span.end),
+        )
+    }
+
+    /// Format the diagnostic like rustc does, quoting the offending line of
+    /// `source` and underlining the span. `path` names the file in the
+    /// output.
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let (start, _) = self.location(source);
+        let line_start = self.span.start.min(source.len());
+        let line_start = source[..line_start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[line_start..]
+            .find('\n')
+            .map_or(source.len(), |i| line_start + i);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+
+        // Underline up to the end of the first line the span covers.
+        let end = self
+            .span
+            .end
+            .clamp(self.span.start, line_start + text.len());
+        let underline = source
+            .get(self.span.start..end)
+            .map_or(1, |s| s.chars().count())
+            .max(1);
+
+        let number = start.line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!(
+            "{severity}[{code}]: {message}\n\
+             {gutter}--> {path}:{line}:{column}\n\
+             {gutter} |\n\
+             {number} | {text}\n\
+             {gutter} | {padding}{carets}\n",
+            severity = self.severity,
+            code = self.code,
+            message = self.message,
+            line = start.line,
+            column = start.column,
+            padding = " ".repeat(start.column - 1),
+            carets = "^".repeat(underline),
+        )
+    }
+}
+
+/// Parse `source` and lint it. A preset that fails to parse yields a
+/// single `syntax-error` diagnostic.
+pub fn lint_source(source: &str) -> Vec<Diagnostic> {
+    match Preset::parse(source) {
+        Ok(preset) => lint(&preset),
+        Err(Error::PresetSyntax { line, message }) => {
+            let span = line_span(source, line);
+            vec![Diagnostic::new(
+                Severity::Error,
+                "syntax-error",
+                message,
+                span,
+            )]
+        }
+        Err(err) => vec![Diagnostic::new(
+            Severity::Error,
+            "syntax-error",
+            err.to_string(),
+            Span::default(),
+        )],
+    }
+}
+
+/// Check a parsed preset. Diagnostics are sorted by position; spans refer
+/// to [`Preset::source`].
+pub fn lint(preset: &Preset) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    check_parameters(&mut diagnostics, &preset.parameters, "", PARAMETERS, "key");
+    for wave in &preset.waves {
+        let prefix = format!("wavecode_{}_", wave.index);
+        check_parameters(
+            &mut diagnostics,
+            &wave.parameters,
+            &prefix,
+            WAVE_PARAMETERS,
+            "custom wave setting",
+        );
+    }
+    for shape in &preset.shapes {
+        let prefix = format!("shapecode_{}_", shape.index);
+        check_parameters(
+            &mut diagnostics,
+            &shape.parameters,
+            &prefix,
+            SHAPE_PARAMETERS,
+            "custom shape setting",
+        );
+    }
+
+    let blocks = blocks(preset);
+    for block in &blocks {
+        check_numbering(&mut diagnostics, block);
+    }
+
+    // Parse the code of every block, then check variables per context.
+    // Contexts with a syntax error are skipped, since the unparsed code may
+    // be what assigns or reads a variable.
+    let mut programs: Vec<(&Block, Program)> = Vec::new();
+    let mut broken: Vec<Context> = Vec::new();
+    for block in &blocks {
+        let Some(context) = block.context else {
+            continue;
+        };
+        match Program::parse_equations(block.equations) {
+            Ok(program) => programs.push((block, program)),
+            Err(err) => {
+                let (span, message) = match err {
+                    Error::ExpressionSyntax { span, message } => (span, message),
+                    err => (Span::default(), err.to_string()),
+                };
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    "syntax-error",
+                    message,
+                    span,
+                ));
+                broken.push(context);
+            }
+        }
+    }
+
+    let mut contexts: Vec<Context> = Vec::new();
+    for (block, _) in &programs {
+        let context = block.context.expect("only code blocks are parsed");
+        if !contexts.contains(&context) && !broken.contains(&context) {
+            contexts.push(context);
+        }
+    }
+    for context in contexts {
+        let group: Vec<&Program> = programs
+            .iter()
+            .filter(|(block, _)| block.context == Some(context))
+            .map(|(_, program)| program)
+            .collect();
+        check_undefined(&mut diagnostics, context, &group);
+    }
+
+    if broken.is_empty() {
+        check_unused_q(&mut diagnostics, preset, &programs);
+    }
+
+    let has_code = !broken.is_empty()
+        || programs
+            .iter()
+            .any(|(_, program)| !program.statements.is_empty());
+    let is_default = |parameter: &Parameter| {
+        let default = frame_variable(&parameter.key).map(|(_, default)| default);
+        default.is_some() && parameter.as_f64() == default
+    };
+    let is_empty = preset.parameters.iter().all(is_default)
+        && preset.waves.is_empty()
+        && preset.shapes.is_empty()
+        && preset.warp_shader.is_empty()
+        && preset.comp_shader.is_empty()
+        && !has_code;
+    if is_empty {
+        let span = preset.header.as_ref().map_or(Span::default(), |h| h.span);
+        diagnostics.push(Diagnostic::warning(
+            "empty-preset",
+            "preset has no equations and leaves every parameter at its default".to_string(),
+            span,
+        ));
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.span.start, diagnostic.span.end));
+    diagnostics
+}
+
+/// The range of values a setting accepts.
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    min: f64,
+    max: f64,
+    integer: bool,
+}
+
+const UNIT: Option<Limit> = Some(Limit {
+    min: 0.0,
+    max: 1.0,
+    integer: false,
+});
+const BOOL: Option<Limit> = Some(Limit {
+    min: 0.0,
+    max: 1.0,
+    integer: true,
+});
+
+const fn range(min: f64, max: f64) -> Option<Limit> {
+    Some(Limit {
+        min,
+        max,
+        integer: false,
+    })
+}
+
+const fn integer(min: f64, max: f64) -> Option<Limit> {
+    Some(Limit {
+        min,
+        max,
+        integer: true,
+    })
+}
+
+/// Top-level keys MilkDrop reads, lowercase.
+const PARAMETERS: &[(&str, Option<Limit>)] = &[
+    ("milkdrop_preset_version", None),
+    ("psversion", None),
+    ("psversion_warp", None),
+    ("psversion_comp", None),
+    ("frating", range(0.0, 5.0)),
+    ("fgammaadj", None),
+    ("fdecay", UNIT),
+    ("fvideoechozoom", None),
+    ("fvideoechoalpha", UNIT),
+    ("nvideoechoorientation", integer(0.0, 3.0)),
+    ("nwavemode", integer(0.0, 7.0)),
+    ("badditivewaves", BOOL),
+    ("bwavedots", BOOL),
+    ("bwavethick", BOOL),
+    ("bmodwavealphabyvolume", BOOL),
+    ("bmaximizewavecolor", BOOL),
+    ("btexwrap", BOOL),
+    ("bdarkencenter", BOOL),
+    ("bredbluestereo", BOOL),
+    ("bbrighten", BOOL),
+    ("bdarken", BOOL),
+    ("bsolarize", BOOL),
+    ("binvert", BOOL),
+    ("fwavealpha", None),
+    ("fwavescale", None),
+    ("fwavesmoothing", UNIT),
+    ("fwaveparam", None),
+    ("fmodwavealphastart", None),
+    ("fmodwavealphaend", None),
+    ("fwarpanimspeed", None),
+    ("fwarpscale", None),
+    ("fzoomexponent", None),
+    ("fshader", UNIT),
+    ("zoom", None),
+    ("rot", None),
+    ("cx", None),
+    ("cy", None),
+    ("dx", None),
+    ("dy", None),
+    ("warp", None),
+    ("sx", None),
+    ("sy", None),
+    ("wave_r", UNIT),
+    ("wave_g", UNIT),
+    ("wave_b", UNIT),
+    ("wave_x", UNIT),
+    ("wave_y", UNIT),
+    ("ob_size", range(0.0, 0.5)),
+    ("ob_r", UNIT),
+    ("ob_g", UNIT),
+    ("ob_b", UNIT),
+    ("ob_a", UNIT),
+    ("ib_size", range(0.0, 0.5)),
+    ("ib_r", UNIT),
+    ("ib_g", UNIT),
+    ("ib_b", UNIT),
+    ("ib_a", UNIT),
+    ("nmotionvectorsx", integer(0.0, 64.0)),
+    ("nmotionvectorsy", integer(0.0, 48.0)),
+    ("mv_dx", None),
+    ("mv_dy", None),
+    ("mv_l", None),
+    ("mv_r", UNIT),
+    ("mv_g", UNIT),
+    ("mv_b", UNIT),
+    ("mv_a", UNIT),
+    ("b1n", None),
+    ("b2n", None),
+    ("b3n", None),
+    ("b1x", None),
+    ("b2x", None),
+    ("b3x", None),
+    ("b1ed", None),
+];
+
+/// Keys of `wavecode_N_<key>`, lowercase.
+const WAVE_PARAMETERS: &[(&str, Option<Limit>)] = &[
+    ("enabled", BOOL),
+    ("samples", integer(0.0, 512.0)),
+    ("sep", None),
+    ("bspectrum", BOOL),
+    ("busedots", BOOL),
+    ("bdrawthick", BOOL),
+    ("badditive", BOOL),
+    ("scaling", None),
+    ("smoothing", UNIT),
+    ("r", UNIT),
+    ("g", UNIT),
+    ("b", UNIT),
+    ("a", UNIT),
+    ("x", None),
+    ("y", None),
+];
+
+/// Keys of `shapecode_N_<key>`, lowercase.
+const SHAPE_PARAMETERS: &[(&str, Option<Limit>)] = &[
+    ("enabled", BOOL),
+    ("sides", integer(3.0, 100.0)),
+    ("additive", BOOL),
+    ("thickoutline", BOOL),
+    ("textured", BOOL),
+    ("num_inst", integer(1.0, 1024.0)),
+    ("tex_zoom", None),
+    ("tex_ang", None),
+    ("x", None),
+    ("y", None),
+    ("rad", None),
+    ("ang", None),
+    ("r", UNIT),
+    ("g", UNIT),
+    ("b", UNIT),
+    ("a", UNIT),
+    ("r2", UNIT),
+    ("g2", UNIT),
+    ("b2", UNIT),
+    ("a2", UNIT),
+    ("border_r", UNIT),
+    ("border_g", UNIT),
+    ("border_b", UNIT),
+    ("border_a", UNIT),
+    ("image", None),
+];
+
+/// Variables per-pixel code may read besides the per-frame ones.
+const PIXEL_VARIABLES: &[&str] = &["x", "y", "rad", "ang"];
+
+/// Variables custom wave code may read.
+const WAVE_VARIABLES: &[&str] = &[
+    "sample",
+    "value1",
+    "value2",
+    "x",
+    "y",
+    "r",
+    "g",
+    "b",
+    "a",
+    "samples",
+    "sep",
+    "scaling",
+    "smoothing",
+    "enabled",
+];
+
+/// Variables custom shape code may read.
+const SHAPE_VARIABLES: &[&str] = &[
+    "x", "y", "rad", "ang", "r", "g", "b", "a", "r2", "g2", "b2", "a2", "border_r", "border_g",
+    "border_b", "border_a", "sides", "thick", "additive", "textured", "tex_zoom", "tex_ang",
+    "num_inst", "instance", "enabled",
+];
+
+/// Which set of variables a block of code runs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Frame,
+    Pixel,
+    Wave(u32),
+    Shape(u32),
+}
+
+/// A run of numbered lines, e.g. every `per_frame_N`.
+struct Block<'a> {
+    /// Key of line `N` without `N`, e.g. `per_frame_` or `wave_0_init`.
+    prefix: String,
+    equations: &'a [Equation],
+    /// `None` for shader code, which isn't checked beyond its numbering.
+    context: Option<Context>,
+}
+
+fn blocks(preset: &Preset) -> Vec<Block<'_>> {
+    let block = |prefix: &str, equations, context| Block {
+        prefix: prefix.to_string(),
+        equations,
+        context,
+    };
+
+    let mut blocks = vec![
+        block(
+            "per_frame_init_",
+            &preset.per_frame_init,
+            Some(Context::Frame),
+        ),
+        block("per_frame_", &preset.per_frame, Some(Context::Frame)),
+        block("per_pixel_", &preset.per_pixel, Some(Context::Pixel)),
+    ];
+    for wave in &preset.waves {
+        let n = wave.index;
+        let context = Some(Context::Wave(n));
+        blocks.push(block(&format!("wave_{n}_init"), &wave.init, context));
+        blocks.push(block(
+            &format!("wave_{n}_per_frame"),
+            &wave.per_frame,
+            context,
+        ));
+        blocks.push(block(
+            &format!("wave_{n}_per_point"),
+            &wave.per_point,
+            context,
+        ));
+    }
+    for shape in &preset.shapes {
+        let n = shape.index;
+        let context = Some(Context::Shape(n));
+        blocks.push(block(&format!("shape_{n}_init"), &shape.init, context));
+        blocks.push(block(
+            &format!("shape_{n}_per_frame"),
+            &shape.per_frame,
+            context,
+        ));
+    }
+    blocks.push(block("warp_", &preset.warp_shader, None));
+    blocks.push(block("comp_", &preset.comp_shader, None));
+    blocks
+}
+
+fn check_parameters(
+    diagnostics: &mut Vec<Diagnostic>,
+    parameters: &[Parameter],
+    prefix: &str,
+    known: &[(&str, Option<Limit>)],
+    what: &str,
+) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for parameter in parameters {
+        let key = parameter.key.to_ascii_lowercase();
+        let name = format!("{prefix}{}", parameter.key);
+
+        if let Some(line) = seen.get(&key) {
+            diagnostics.push(Diagnostic::warning(
+                "duplicate-key",
+                format!("`{name}` is already set on line {line}; this value is ignored"),
+                parameter.span,
+            ));
+            continue;
+        }
+        seen.insert(key.clone(), parameter.line);
+
+        let Some((_, limit)) = known.iter().find(|(known, _)| *known == key) else {
+            diagnostics.push(Diagnostic::warning(
+                "unknown-key",
+                format!("unknown {what} `{name}`"),
+                parameter.key_span,
+            ));
+            continue;
+        };
+
+        let Some(value) = parameter.as_f64() else {
+            diagnostics.push(Diagnostic::warning(
+                "invalid-value",
+                format!(
+                    "`{name}` should be a number, found `{}`",
+                    parameter.value.trim()
+                ),
+                parameter.value_span,
+            ));
+            continue;
+        };
+
+        if let Some(limit) = limit {
+            let in_range = value >= limit.min && value <= limit.max;
+            if !in_range || (limit.integer && value.fract() != 0.0) {
+                let kind = if limit.integer {
+                    "an integer"
+                } else {
+                    "a value"
+                };
+                diagnostics.push(Diagnostic::warning(
+                    "out-of-range",
+                    format!(
+                        "`{name}` should be {kind} from {} to {}, found {value}",
+                        limit.min, limit.max
+                    ),
+                    parameter.value_span,
+                ));
+            }
+        }
+    }
+}
+
+/// Duplicate line numbers, and gaps after which MilkDrop stops reading.
+///
+/// Lines past a gap that hold nothing but a `//` comment are fine; presets
+/// commonly park comments at `per_frame_1000` and up.
+fn check_numbering(diagnostics: &mut Vec<Diagnostic>, block: &Block) {
+    let mut sorted: Vec<&Equation> = block.equations.iter().collect();
+    sorted.sort_by_key(|equation| (equation.index, equation.line));
+
+    let mut expected = 1;
+    let mut missing = None;
+    let mut gap_reported = false;
+    let mut previous: Option<&Equation> = None;
+    for equation in sorted {
+        let key = format!("{}{}", block.prefix, equation.index);
+
+        match previous {
+            Some(previous) if previous.index == equation.index => {
+                diagnostics.push(Diagnostic::warning(
+                    "duplicate-index",
+                    format!(
+                        "`{key}` is already defined on line {}; this line is ignored",
+                        previous.line
+                    ),
+                    equation.span,
+                ));
+                continue;
+            }
+            _ => {}
+        }
+
+        if equation.index != expected && missing.is_none() && !gap_reported {
+            missing = Some(expected);
+        }
+        expected = equation.index.saturating_add(1);
+        previous = Some(equation);
+
+        let code = equation.code.trim();
+        if code.is_empty() || code.starts_with("//") {
+            continue;
+        }
+        // One warning per gap is enough.
+        if let Some(index) = missing.take() {
+            let message = format!(
+                "`{key}` follows a gap: `{}{index}` is missing, so MilkDrop ignores this \
+                 and every later line",
+                block.prefix
+            );
+            diagnostics.push(Diagnostic::warning("numbering-gap", message, equation.span));
+            gap_reported = true;
+        }
+    }
+}
+
+/// The variables each expression reads and writes, lowercase.
+#[derive(Default)]
+struct Usage<'a> {
+    reads: Vec<(String, &'a Expr)>,
+    writes: Vec<(String, &'a Expr)>,
+}
+
+impl<'a> Usage<'a> {
+    fn of(program: &'a Program) -> Self {
+        let mut usage = Usage::default();
+        for statement in &program.statements {
+            usage.collect(statement);
+        }
+        usage
+    }
+
+    fn collect(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.reads.push((name.to_ascii_lowercase(), expr)),
+            ExprKind::Assign { target, value, .. } => {
+                match &target.kind {
+                    ExprKind::Variable(name) => {
+                        self.writes
+                            .push((name.to_ascii_lowercase(), target.as_ref()));
+                    }
+                    _ => self.collect(target),
+                }
+                self.collect(value);
+            }
+            ExprKind::Number(_) => {}
+            ExprKind::Unary { operand, .. } => self.collect(operand),
+            ExprKind::Binary { left, right, .. } => {
+                self.collect(left);
+                self.collect(right);
+            }
+            ExprKind::Conditional {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.collect(condition);
+                self.collect(then);
+                self.collect(otherwise);
+            }
+            ExprKind::Call { args, .. } => args.iter().for_each(|arg| self.collect(arg)),
+        }
+    }
+}
+
+fn is_q_variable(name: &str) -> bool {
+    name.strip_prefix('q')
+        .and_then(|n| n.parse::<usize>().ok())
+        .map_or(false, |n| {
+            (1..=Q_VARIABLES).contains(&n) && !name.starts_with("q0")
+        })
+}
+
+fn is_t_variable(name: &str) -> bool {
+    matches!(name, "t1" | "t2" | "t3" | "t4" | "t5" | "t6" | "t7" | "t8")
+}
+
+/// Reads of variables that nothing in the same context ever assigns.
+fn check_undefined(diagnostics: &mut Vec<Diagnostic>, context: Context, programs: &[&Program]) {
+    let usages: Vec<Usage> = programs.iter().map(|program| Usage::of(program)).collect();
+    let assigned: HashSet<&str> = usages
+        .iter()
+        .flat_map(|usage| usage.writes.iter().map(|(name, _)| name.as_str()))
+        .collect();
+
+    let is_builtin = |name: &str| {
+        let shared = INPUT_VARIABLES.contains(&name) || is_q_variable(name);
+        shared
+            || match context {
+                Context::Frame => FRAME_VARIABLES.iter().any(|(frame, _)| *frame == name),
+                Context::Pixel => {
+                    FRAME_VARIABLES.iter().any(|(frame, _)| *frame == name)
+                        || PIXEL_VARIABLES.contains(&name)
+                }
+                Context::Wave(_) => WAVE_VARIABLES.contains(&name) || is_t_variable(name),
+                Context::Shape(_) => SHAPE_VARIABLES.contains(&name) || is_t_variable(name),
+            }
+    };
+
+    let mut reported = HashSet::new();
+    for usage in &usages {
+        for (name, expr) in &usage.reads {
+            if is_builtin(name) || assigned.contains(name.as_str()) {
+                continue;
+            }
+            if reported.insert(name.clone()) {
+                diagnostics.push(Diagnostic::warning(
+                    "undefined-variable",
+                    format!("`{name}` is never assigned and always reads as 0"),
+                    expr.span,
+                ));
+            }
+        }
+    }
+}
+
+/// `q` variables set by per-frame code that nothing reads.
+fn check_unused_q(
+    diagnostics: &mut Vec<Diagnostic>,
+    preset: &Preset,
+    programs: &[(&Block, Program)],
+) {
+    let usages: Vec<(Option<Context>, Usage)> = programs
+        .iter()
+        .map(|(block, program)| (block.context, Usage::of(program)))
+        .collect();
+
+    let mut read: HashSet<String> = usages
+        .iter()
+        .flat_map(|(_, usage)| usage.reads.iter().map(|(name, _)| name.clone()))
+        .filter(|name| is_q_variable(name))
+        .collect();
+    for line in preset.warp_shader.iter().chain(&preset.comp_shader) {
+        let words = line
+            .code
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map(str::to_ascii_lowercase)
+            .filter(|word| is_q_variable(word));
+        read.extend(words);
+    }
+
+    let mut reported = HashSet::new();
+    for (context, usage) in &usages {
+        if *context != Some(Context::Frame) {
+            continue;
+        }
+        for (name, expr) in &usage.writes {
+            if is_q_variable(name) && !read.contains(name) && reported.insert(name.clone()) {
+                diagnostics.push(Diagnostic::warning(
+                    "unused-q",
+                    format!("`{name}` is set but never read by the rest of the preset"),
+                    expr.span,
+                ));
+            }
+        }
+    }
+}
+
+/// The span of 1-based line `number` of `source`, without its terminator.
+fn line_span(source: &str, number: usize) -> Span {
+    let mut start = 0;
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        if i + 1 == number {
+            let text = line.trim_end_matches(['\n', '\r']);
+            return Span::new(start, start + text.len());
+        }
+        start += line.len();
+    }
+    Span::new(source.len(), source.len())
+}
@@ -0,0 +1,174 @@
+use crate::core::ProjectM;
+use crate::error::Result;
+
+/// Configuration of a projectM instance.
+///
+/// Every field is optional: `None` leaves libprojectM's own default (or the
+/// instance's current value) untouched. With the `serde` feature enabled the
+/// settings can be stored as TOML, JSON and the like; missing keys
+/// deserialize to `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ProjectMSettings {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub mesh_size: Option<(usize, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub fps: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub beat_sensitivity: Option<f32>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub hard_cut_enabled: Option<bool>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub hard_cut_duration: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub hard_cut_sensitivity: Option<f32>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub soft_cut_duration: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub preset_duration: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub preset_locked: Option<bool>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub aspect_correction: Option<bool>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub easter_egg: Option<f32>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub window_size: Option<(usize, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub texture_search_paths: Option<Vec<String>>,
+}
+
+impl ProjectMSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take every value set in `other`, keeping ours where `other` has none.
+    pub fn merge(&mut self, other: &ProjectMSettings) {
+        macro_rules! merge {
+            ($($field:ident),*) => {
+                $(
+                    if other.$field.is_some() {
+                        self.$field = other.$field.clone();
+                    }
+                )*
+            };
+        }
+
+        merge!(
+            mesh_size,
+            fps,
+            beat_sensitivity,
+            hard_cut_enabled,
+            hard_cut_duration,
+            hard_cut_sensitivity,
+            soft_cut_duration,
+            preset_duration,
+            preset_locked,
+            aspect_correction,
+            easter_egg,
+            window_size,
+            texture_search_paths
+        );
+    }
+}
+
+/// Builder for a configured [`ProjectM`] instance, see
+/// [`ProjectM::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct ProjectMBuilder {
+    settings: ProjectMSettings,
+}
+
+impl ProjectMBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply all values set in `settings`, e.g. loaded user preferences.
+    ///
+    /// Values set on the builder afterwards take precedence.
+    pub fn settings(mut self, settings: &ProjectMSettings) -> Self {
+        self.settings.merge(settings);
+        self
+    }
+
+    pub fn mesh_size(mut self, mesh_x: usize, mesh_y: usize) -> Self {
+        self.settings.mesh_size = Some((mesh_x, mesh_y));
+        self
+    }
+
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.settings.fps = Some(fps);
+        self
+    }
+
+    pub fn beat_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.settings.beat_sensitivity = Some(sensitivity);
+        self
+    }
+
+    pub fn hard_cut_enabled(mut self, enabled: bool) -> Self {
+        self.settings.hard_cut_enabled = Some(enabled);
+        self
+    }
+
+    pub fn hard_cut_duration(mut self, seconds: f64) -> Self {
+        self.settings.hard_cut_duration = Some(seconds);
+        self
+    }
+
+    pub fn hard_cut_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.settings.hard_cut_sensitivity = Some(sensitivity);
+        self
+    }
+
+    pub fn soft_cut_duration(mut self, seconds: f64) -> Self {
+        self.settings.soft_cut_duration = Some(seconds);
+        self
+    }
+
+    pub fn preset_duration(mut self, seconds: f64) -> Self {
+        self.settings.preset_duration = Some(seconds);
+        self
+    }
+
+    pub fn preset_locked(mut self, lock: bool) -> Self {
+        self.settings.preset_locked = Some(lock);
+        self
+    }
+
+    pub fn aspect_correction(mut self, enabled: bool) -> Self {
+        self.settings.aspect_correction = Some(enabled);
+        self
+    }
+
+    pub fn easter_egg(mut self, sensitivity: f32) -> Self {
+        self.settings.easter_egg = Some(sensitivity);
+        self
+    }
+
+    pub fn window_size(mut self, width: usize, height: usize) -> Self {
+        self.settings.window_size = Some((width, height));
+        self
+    }
+
+    pub fn texture_search_paths<S: Into<String>>(
+        mut self,
+        paths: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.settings.texture_search_paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Create the instance and apply the configured settings.
+    ///
+    /// If any setting is rejected, the instance is destroyed again and the
+    /// error is returned.
+    pub fn build(self) -> Result<ProjectM> {
+        let projectm = ProjectM::create()?;
+        projectm.apply_settings(&self.settings)?;
+        Ok(projectm)
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(feature = "native")]
+extern crate projectm_sys as ffi;
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "native")]
+use crate::core::ProjectM;
+use crate::error::{Error, Result};
+
+/// A libprojectM release version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// The version of the libprojectM headers the bindings were generated
+    /// from.
+    #[cfg(feature = "native")]
+    pub fn bindings() -> Version {
+        ffi::BINDINGS_VERSION
+            .parse()
+            .expect("projectm-sys reports an invalid bindings version")
+    }
+
+    /// The version of the libprojectM library loaded at runtime.
+    #[cfg(feature = "native")]
+    pub fn runtime() -> Result<Version> {
+        let (major, minor, patch) = ProjectM::get_version_components();
+        let component = |value: i32| u32::try_from(value).map_err(|_| Error::OutOfRange("version"));
+
+        Ok(Version::new(
+            component(major)?,
+            component(minor)?,
+            component(patch)?,
+        ))
+    }
+
+    /// Whether a library of this version provides everything `required`
+    /// offers, i.e. it has the same major version and is not older.
+    pub fn is_compatible_with(&self, required: &Version) -> bool {
+        self.major == required.major && self >= required
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    /// Parse `major.minor.patch`, ignoring any pre-release or build suffix
+    /// such as `-dev` or `+abc123`. A missing patch component counts as 0.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidVersion(s.to_string());
+
+        let core = s.trim().split(['-', '+']).next().unwrap_or_default();
+        let mut components = core.split('.').map(|c| c.parse::<u32>());
+
+        let major = components.next().and_then(|c| c.ok()).ok_or_else(invalid)?;
+        let minor = components.next().and_then(|c| c.ok()).ok_or_else(invalid)?;
+        let patch = match components.next() {
+            Some(patch) => patch.map_err(|_| invalid())?,
+            None => 0,
+        };
+        if components.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Version::new(major, minor, patch))
+    }
+}
+
+/// Check that the libprojectM loaded at runtime can serve the bindings.
+///
+/// Returns the runtime version, or [`Error::IncompatibleVersion`] if the
+/// library is older than the headers the bindings were generated from or
+/// has a different major version.
+#[cfg(feature = "native")]
+pub fn check_runtime_version() -> Result<Version> {
+    let runtime = Version::runtime()?;
+    let bindings = Version::bindings();
+
+    if runtime.is_compatible_with(&bindings) {
+        Ok(runtime)
+    } else {
+        Err(Error::IncompatibleVersion { runtime, bindings })
+    }
+}
@@ -0,0 +1,18 @@
+#[cfg(test)]
+mod compile_fail {
+    /// Thread-safety guarantees that must not regress.
+    #[test]
+    fn compile_fail() {
+        let cases = trybuild::TestCases::new();
+        cases.compile_fail("tests/compile_fail/*.rs");
+    }
+
+    fn assert_send<T: Send>() {}
+
+    /// The handles meant for other threads.
+    #[test]
+    fn handles_are_send() {
+        assert_send::<projectm::pcm::PcmSender>();
+        assert_send::<projectm::playlist::PlaylistRemote>();
+    }
+}
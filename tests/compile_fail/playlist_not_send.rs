@@ -0,0 +1,8 @@
+mod support;
+
+use projectm::playlist::Playlist;
+use support::{IsSend, SendCheck};
+
+fn main() {
+    let _: IsSend = <Playlist as SendCheck<_>>::check();
+}
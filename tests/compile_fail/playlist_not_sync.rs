@@ -0,0 +1,8 @@
+mod support;
+
+use projectm::playlist::Playlist;
+use support::{IsSync, SyncCheck};
+
+fn main() {
+    let _: IsSync = <Playlist as SyncCheck<_>>::check();
+}
@@ -0,0 +1,8 @@
+mod support;
+
+use projectm::core::ProjectM;
+use support::{IsSend, SendCheck};
+
+fn main() {
+    let _: IsSend = <ProjectM as SendCheck<_>>::check();
+}
@@ -0,0 +1,8 @@
+mod support;
+
+use projectm::core::ProjectM;
+use support::{IsSync, SyncCheck};
+
+fn main() {
+    let _: IsSync = <ProjectM as SyncCheck<_>>::check();
+}
@@ -0,0 +1,46 @@
+//! Thread-safety checks for the compile-fail cases.
+//!
+//! Asserting `T: Send` directly makes rustc list every private field that is
+//! not `Send`. Instead, `<T as SendCheck<_>>::check()` leaves the trait
+//! parameter to inference: for a `Send` type both blanket impls apply and
+//! the `IsSend` annotation picks one, while for any other type only the
+//! `NotSend` impl applies, so `_` is inferred as `NotSend` and rustc reports
+//! a single "expected `IsSend`, found `NotSend`" mismatch.
+#![allow(dead_code)]
+
+pub struct IsSend;
+pub struct NotSend;
+pub struct IsSync;
+pub struct NotSync;
+
+pub trait SendCheck<A> {
+    fn check() -> A;
+}
+
+impl<T: ?Sized> SendCheck<NotSend> for T {
+    fn check() -> NotSend {
+        NotSend
+    }
+}
+
+impl<T: ?Sized + Send> SendCheck<IsSend> for T {
+    fn check() -> IsSend {
+        IsSend
+    }
+}
+
+pub trait SyncCheck<A> {
+    fn check() -> A;
+}
+
+impl<T: ?Sized> SyncCheck<NotSync> for T {
+    fn check() -> NotSync {
+        NotSync
+    }
+}
+
+impl<T: ?Sized + Sync> SyncCheck<IsSync> for T {
+    fn check() -> IsSync {
+        IsSync
+    }
+}
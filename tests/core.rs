@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod core {
     use projectm::core::*;
+    use projectm::settings::ProjectMSettings;
+    use projectm::{check_runtime_version, Error, Event, Version};
     use std::process::Command;
+    use std::rc::Rc;
     use std::str;
 
     fn get_git_hash_by_command() -> Option<String> {
@@ -22,16 +25,281 @@ mod core {
 
     #[test]
     fn test_get_versions() {
-        let version_tuple = ProjectM::get_version_components();
-        assert_eq!(version_tuple, (4, 0, 0));
+        let version = check_runtime_version().unwrap();
+        assert!(version.is_compatible_with(&Version::bindings()));
+        assert_eq!(ProjectM::get_version().unwrap(), version);
 
-        let version_string = ProjectM::get_version_string();
-        assert_eq!(version_string, "4.0.0");
+        let (major, minor, patch) = ProjectM::get_version_components();
+        assert_eq!(
+            Version::new(major as u32, minor as u32, patch as u32),
+            version
+        );
 
-        let vcs_version_string = ProjectM::get_vcs_version_string();
+        let version_string = ProjectM::get_version_string().unwrap();
+        assert_eq!(version_string.parse::<Version>().unwrap(), version);
+
+        let vcs_version_string = ProjectM::get_vcs_version_string().unwrap();
         assert_eq!(vcs_version_string, get_git_hash_by_command().unwrap());
     }
 
+    #[test]
+    fn test_version() {
+        assert_eq!("4.1.0".parse::<Version>().unwrap(), Version::new(4, 1, 0));
+        assert_eq!("4.2".parse::<Version>().unwrap(), Version::new(4, 2, 0));
+        assert_eq!(
+            "4.1.2-dev+abc".parse::<Version>().unwrap(),
+            Version::new(4, 1, 2)
+        );
+        for invalid in ["", "4", "4.x.0", "4.0.0.1", "v4.0.0"] {
+            assert!(matches!(
+                invalid.parse::<Version>(),
+                Err(Error::InvalidVersion(_))
+            ));
+        }
+
+        assert_eq!(Version::new(4, 0, 1).to_string(), "4.0.1");
+        assert!(Version::new(4, 10, 0) > Version::new(4, 9, 3));
+
+        let bindings = Version::new(4, 1, 0);
+        assert!(Version::new(4, 1, 0).is_compatible_with(&bindings));
+        assert!(Version::new(4, 2, 1).is_compatible_with(&bindings));
+        assert!(!Version::new(4, 0, 0).is_compatible_with(&bindings));
+        assert!(!Version::new(5, 0, 0).is_compatible_with(&bindings));
+
+        assert!(Version::bindings() >= Version::new(4, 0, 0));
+    }
+
+    #[test]
+    fn test_callbacks_are_dropped() {
+        let projectm = ProjectM::create().unwrap();
+        let token = Rc::new(());
+
+        let captured = token.clone();
+        projectm.set_preset_switch_requested_event_callback(move |_| {
+            let _ = &captured;
+        });
+        assert_eq!(Rc::strong_count(&token), 2);
+
+        // Replacing the callback drops the previous closure.
+        let captured = token.clone();
+        projectm.set_preset_switch_requested_event_callback(move |_| {
+            let _ = &captured;
+        });
+        assert_eq!(Rc::strong_count(&token), 2);
+
+        projectm.clear_preset_switch_requested_event_callback();
+        assert_eq!(Rc::strong_count(&token), 1);
+
+        let captured = token.clone();
+        projectm.set_preset_switch_failed_event_callback(move |_, _| {
+            let _ = &captured;
+        });
+        assert_eq!(Rc::strong_count(&token), 2);
+
+        // Dropping the instance drops the remaining callbacks.
+        drop(projectm);
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn test_events() {
+        let projectm = ProjectM::create().unwrap();
+        let events = projectm.events();
+
+        projectm
+            .load_preset_file("presets/does-not-exist.milk", false)
+            .unwrap();
+
+        let failed = events
+            .try_iter()
+            .any(|event| matches!(event, Event::PresetSwitchFailed { .. }));
+        assert!(failed);
+    }
+
+    #[test]
+    fn test_channels() {
+        assert_eq!(Channels::try_from(1).unwrap(), Channels::Mono);
+        assert_eq!(Channels::try_from(2).unwrap(), Channels::Stereo);
+        assert!(Channels::try_from(3).is_err());
+
+        assert_eq!("stereo".parse::<Channels>().unwrap(), Channels::Stereo);
+        assert_eq!("Mono".parse::<Channels>().unwrap(), Channels::Mono);
+        assert_eq!("2".parse::<Channels>().unwrap(), Channels::Stereo);
+        assert!("surround".parse::<Channels>().is_err());
+
+        for channels in Channels::ALL {
+            assert_eq!(channels.to_string().parse::<Channels>().unwrap(), channels);
+        }
+    }
+
+    #[test]
+    fn test_touch_type() {
+        assert_eq!(TouchType::try_from(2).unwrap(), TouchType::RadialBlob);
+        assert!(TouchType::try_from(9).is_err());
+
+        assert_eq!(TouchType::DoubleLine.to_string(), "double_line");
+        assert_eq!(
+            "derivative_line".parse::<TouchType>().unwrap(),
+            TouchType::DerivativeLine
+        );
+
+        for (value, touch_type) in TouchType::ALL.into_iter().enumerate() {
+            assert_eq!(touch_type as u32, value as u32);
+            assert_eq!(
+                touch_type.to_string().parse::<TouchType>().unwrap(),
+                touch_type
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_enums_serde() {
+        assert_eq!(
+            serde_json::to_string(&Channels::Stereo).unwrap(),
+            "\"stereo\""
+        );
+        assert_eq!(
+            serde_json::from_str::<TouchType>("\"radial_blob\"").unwrap(),
+            TouchType::RadialBlob
+        );
+    }
+
+    #[test]
+    fn test_pcm_sample_conversion() {
+        use projectm::pcm::Sample;
+
+        assert_eq!(0.5f32.to_f32(), 0.5);
+        assert_eq!(0.25f64.to_f32(), 0.25);
+        assert_eq!(i16::MIN.to_f32(), -1.0);
+        assert_eq!(i32::MIN.to_f32(), -1.0);
+        assert_eq!(128u8.to_f32(), 0.0);
+        assert_eq!(0u8.to_f32(), -1.0);
+    }
+
+    #[test]
+    fn test_pcm_add() {
+        let projectm = ProjectM::create().unwrap();
+        let max = ProjectM::pcm_get_max_samples() as usize;
+
+        // Longer than a single call accepts, with an odd stereo tail.
+        let len = max * 5 + 1;
+        let floats: Vec<f32> = (0..len).map(|i| (i as f32 / 100.0).sin()).collect();
+        projectm.pcm_add(&floats, Channels::Stereo);
+        projectm.pcm_add(&floats, Channels::Mono);
+        projectm.pcm_add_float(&floats[..1], Channels::Stereo);
+
+        let ints: Vec<i16> = (0..len).map(|i| i as i16).collect();
+        projectm.pcm_add_int16(&ints, Channels::Stereo);
+
+        let bytes: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        projectm.pcm_add_uint8(&bytes, Channels::Stereo);
+
+        let wide: Vec<i32> = (0..len).map(|i| (i as i32) << 16).collect();
+        projectm.pcm_add(&wide, Channels::Stereo);
+
+        let doubles: Vec<f64> = floats.iter().map(|&x| x as f64).collect();
+        projectm.pcm_add(&doubles, Channels::Mono);
+    }
+
+    #[test]
+    fn test_pcm_sender() {
+        let projectm = ProjectM::create().unwrap();
+        let mut sender = projectm.pcm_sender(Channels::Stereo, 1023);
+        assert_eq!(sender.channels(), Channels::Stereo);
+        assert_eq!(sender.free(), 1024);
+
+        let sender = std::thread::spawn(move || {
+            // Only whole frames that fit are queued.
+            assert_eq!(sender.send(&[0i16; 301]), 300);
+            assert_eq!(sender.send(&[0.5f32; 1000]), 724);
+            assert_eq!(sender.send(&[0u8; 2]), 0);
+            sender
+        })
+        .join()
+        .unwrap();
+        assert_eq!(sender.free(), 0);
+
+        projectm.drain_pcm();
+        assert_eq!(sender.free(), 1024);
+        assert!(!sender.is_abandoned());
+
+        drop(projectm);
+        assert!(sender.is_abandoned());
+    }
+
+    #[test]
+    fn test_builder_and_settings() {
+        let projectm = ProjectM::builder()
+            .mesh_size(64, 48)
+            .fps(30)
+            .beat_sensitivity(1.5)
+            .hard_cut_enabled(true)
+            .hard_cut_duration(10.0)
+            .preset_duration(15.0)
+            .preset_locked(true)
+            .window_size(640, 480)
+            .texture_search_paths(["presets"])
+            .build()
+            .unwrap();
+
+        let settings = projectm.settings().unwrap();
+        assert_eq!(settings.mesh_size, Some((64, 48)));
+        assert_eq!(settings.fps, Some(30));
+        assert_eq!(settings.beat_sensitivity, Some(1.5));
+        assert_eq!(settings.hard_cut_enabled, Some(true));
+        assert_eq!(settings.hard_cut_duration, Some(10.0));
+        assert_eq!(settings.preset_duration, Some(15.0));
+        assert_eq!(settings.preset_locked, Some(true));
+        assert_eq!(settings.window_size, Some((640, 480)));
+        assert_eq!(settings.texture_search_paths, Some(vec!["presets".into()]));
+
+        // A snapshot recreates an identically configured instance.
+        let copy = ProjectM::builder().settings(&settings).build().unwrap();
+        assert_eq!(copy.settings().unwrap(), settings);
+
+        // Rejected settings leave the instance untouched.
+        let invalid = ProjectMSettings {
+            fps: Some(u32::MAX),
+            mesh_size: Some((8, 8)),
+            texture_search_paths: Some(vec!["textures".into()]),
+            ..Default::default()
+        };
+        assert!(projectm.apply_settings(&invalid).is_err());
+        assert_eq!(projectm.get_mesh_size(), (64, 48));
+        assert_eq!(
+            projectm.get_texture_search_paths(),
+            vec!["presets".to_string()]
+        );
+        assert!(ProjectM::builder().settings(&invalid).build().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_settings_serde() {
+        let settings: ProjectMSettings = toml::from_str(
+            r#"
+            fps = 60
+            mesh_size = [32, 24]
+            texture_search_paths = ["textures"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.fps, Some(60));
+        assert_eq!(settings.mesh_size, Some((32, 24)));
+        assert_eq!(settings.beat_sensitivity, None);
+
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(
+            json,
+            r#"{"mesh_size":[32,24],"fps":60,"texture_search_paths":["textures"]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ProjectMSettings>(&json).unwrap(),
+            settings
+        );
+    }
+
     // #[test]
     // fn test_create() {
     //     let projectm = ProjectM::create();
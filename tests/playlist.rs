@@ -1,19 +1,446 @@
 #[cfg(test)]
 mod playlist {
     use projectm::core::ProjectM;
-    use projectm::playlist::Playlist;
+    use projectm::playlist::{Playlist, PlaylistCommand, PlaylistFilter, SortOrder, SortPredicate};
+    use projectm::{Error, Event};
+    use std::cell::Cell;
+    use std::path::PathBuf;
+    use std::rc::Rc;
 
     #[test]
     fn playlist() {
-        let projectm = ProjectM::create();
-        let playlist = Playlist::create(projectm);
-        assert_eq!(playlist.is_empty(), true);
+        let projectm = ProjectM::create().unwrap();
+        let playlist = Playlist::create(&projectm).unwrap();
+        assert!(playlist.is_empty());
 
         // add ../presets to playlist
         // get absolute path to ../presets
         let path = std::env::current_dir().unwrap();
         let presets_dir = path.join("presets");
-        playlist.add_path(presets_dir.to_str().unwrap(), true);
+        playlist
+            .add_path(presets_dir.to_str().unwrap(), true)
+            .unwrap();
         assert_eq!(playlist.len(), 20);
     }
+
+    #[test]
+    fn playlist_outlives_projectm() {
+        let projectm = ProjectM::create().unwrap();
+        let playlist = Playlist::create(&projectm).unwrap();
+        drop(projectm);
+
+        let path = std::env::current_dir().unwrap();
+        let presets_dir = path.join("presets");
+        playlist
+            .add_path(presets_dir.to_str().unwrap(), true)
+            .unwrap();
+        assert_eq!(playlist.len(), 20);
+    }
+
+    #[test]
+    fn playlist_index_errors() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = Playlist::create(&projectm).unwrap();
+        assert!(matches!(playlist.play_random(), Err(Error::EmptyPlaylist)));
+
+        let path = std::env::current_dir().unwrap();
+        let presets_dir = path.join("presets");
+        playlist
+            .add_path(presets_dir.to_str().unwrap(), true)
+            .unwrap();
+        assert!(matches!(
+            playlist.set_position(20, true),
+            Err(Error::IndexOutOfRange { index: 20, len: 20 })
+        ));
+        assert!(playlist.add_path("presets\0", true).is_err());
+    }
+
+    #[test]
+    fn playlist_events() {
+        let projectm = ProjectM::create().unwrap();
+        let events = projectm.events();
+        let mut playlist = Playlist::create(&projectm).unwrap();
+
+        let path = std::env::current_dir().unwrap();
+        let presets_dir = path.join("presets");
+        playlist
+            .add_path(presets_dir.to_str().unwrap(), true)
+            .unwrap();
+        playlist.set_position(3, true).unwrap();
+
+        let switched = events.try_iter().any(|event| {
+            matches!(
+                event,
+                Event::PlaylistPresetSwitched {
+                    hard_cut: true,
+                    index: 3
+                }
+            )
+        });
+        assert!(switched);
+    }
+
+    #[test]
+    fn projectm_events_after_playlist_drop() {
+        let projectm = ProjectM::create().unwrap();
+        let events = projectm.events();
+        drop(Playlist::create(&projectm).unwrap());
+
+        projectm
+            .load_preset_file("presets/does-not-exist.milk", false)
+            .unwrap();
+
+        let failed = events
+            .try_iter()
+            .any(|event| matches!(event, Event::PresetSwitchFailed { .. }));
+        assert!(failed);
+    }
+
+    fn presets_dir() -> PathBuf {
+        std::env::current_dir().unwrap().join("presets")
+    }
+
+    fn filled_playlist(projectm: &ProjectM) -> Playlist {
+        let playlist = Playlist::create(projectm).unwrap();
+        assert_eq!(playlist.add_path(presets_dir(), true).unwrap(), 20);
+        playlist
+    }
+
+    #[test]
+    fn playlist_items() {
+        let projectm = ProjectM::create().unwrap();
+        let playlist = filled_playlist(&projectm);
+
+        let items = playlist.items().unwrap();
+        assert_eq!(items.len(), 20);
+        assert_eq!(playlist.item(5).unwrap(), items[5]);
+        assert!(matches!(
+            playlist.item(20),
+            Err(Error::IndexOutOfRange { index: 20, len: 20 })
+        ));
+    }
+
+    #[test]
+    fn playlist_add_and_insert_presets() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = Playlist::create(&projectm).unwrap();
+        let dir = presets_dir();
+
+        assert!(playlist
+            .add_preset(dir.join("000-empty.milk"), false)
+            .unwrap());
+        assert!(!playlist
+            .add_preset(dir.join("000-empty.milk"), false)
+            .unwrap());
+        assert!(playlist
+            .add_preset(dir.join("000-empty.milk"), true)
+            .unwrap());
+        assert_eq!(playlist.len(), 2);
+
+        let added = playlist
+            .add_presets(
+                &[dir.join("001-line.milk"), dir.join("100-square.milk")],
+                false,
+            )
+            .unwrap();
+        assert_eq!(added, 2);
+
+        assert!(playlist
+            .insert_preset(dir.join("200-wave.milk"), 0, false)
+            .unwrap());
+        assert!(playlist.item(0).unwrap().ends_with("200-wave.milk"));
+
+        let added = playlist
+            .insert_presets(
+                &[dir.join("201-wave.milk"), dir.join("202-wave.milk")],
+                1,
+                false,
+            )
+            .unwrap();
+        assert_eq!(added, 2);
+        assert!(playlist.item(2).unwrap().ends_with("202-wave.milk"));
+        assert_eq!(playlist.len(), 7);
+
+        assert!(playlist
+            .insert_preset(dir.join("203-wave.milk"), 8, false)
+            .is_err());
+    }
+
+    #[test]
+    fn playlist_remove_and_clear() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        let second = playlist.item(1).unwrap();
+        playlist.remove_preset(0).unwrap();
+        assert_eq!(playlist.len(), 19);
+        assert_eq!(playlist.item(0).unwrap(), second);
+
+        assert_eq!(playlist.remove_presets(0, 4).unwrap(), 4);
+        assert_eq!(playlist.len(), 15);
+        assert!(playlist.remove_preset(15).is_err());
+
+        playlist.clear();
+        assert!(playlist.is_empty());
+    }
+
+    #[test]
+    fn playlist_sort() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        playlist.sort(SortPredicate::FilenameOnly, SortOrder::Ascending);
+        let items = playlist.items().unwrap();
+        let mut sorted = items.clone();
+        sorted.sort();
+        assert_eq!(items, sorted);
+
+        playlist.sort(SortPredicate::FullPath, SortOrder::Descending);
+        let items = playlist.items().unwrap();
+        sorted.reverse();
+        assert_eq!(items, sorted);
+    }
+
+    #[test]
+    fn playlist_position_and_retry_count() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        playlist.set_position(7, true).unwrap();
+        assert_eq!(playlist.get_position(), 7);
+
+        playlist.set_retry_count(3);
+        assert_eq!(playlist.get_retry_count(), 3);
+    }
+
+    #[test]
+    fn playlist_callbacks() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        let switched = Rc::new(Cell::new(None));
+        let captured = switched.clone();
+        playlist.set_preset_switched_event_callback(move |_, index| captured.set(Some(index)));
+
+        playlist.set_position(4, true).unwrap();
+        assert_eq!(switched.get(), Some(4));
+
+        playlist.clear_preset_switched_event_callback();
+        playlist.set_position(5, true).unwrap();
+        assert_eq!(switched.get(), Some(4));
+        assert_eq!(Rc::strong_count(&switched), 1);
+
+        let failed = Rc::new(Cell::new(false));
+        let captured = failed.clone();
+        playlist.set_preset_switch_failed_event_callback(move |_, _| captured.set(true));
+        playlist.clear_preset_switch_failed_event_callback();
+        assert_eq!(Rc::strong_count(&failed), 1);
+    }
+
+    #[test]
+    fn playlist_filter() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        playlist.set_filter(&["-**/*-wave*.milk"]).unwrap();
+        assert_eq!(playlist.get_filter().unwrap(), vec!["-**/*-wave*.milk"]);
+
+        let removed = playlist.apply_filter();
+        assert_eq!(removed, 11);
+        assert_eq!(playlist.len(), 9);
+    }
+
+    #[test]
+    fn playlist_history() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+        playlist.set_shuffle(false);
+
+        playlist.set_position(2, true).unwrap();
+        playlist.set_position(5, true).unwrap();
+        playlist.play_next();
+        assert_eq!(playlist.history(), &[0, 2, 5, 6]);
+        assert_eq!(playlist.history_cursor(), Some(3));
+
+        playlist.play_prev();
+        assert_eq!(playlist.get_position(), 5);
+        playlist.play_prev();
+        assert_eq!(playlist.get_position(), 2);
+        assert_eq!(playlist.forward(), Some(5));
+        assert_eq!(playlist.get_position(), 5);
+
+        // A new jump discards the forward entries.
+        playlist.set_position(10, true).unwrap();
+        assert_eq!(playlist.history(), &[0, 2, 5, 10]);
+        assert_eq!(playlist.forward(), None);
+
+        // Removing a preset drops it from the history and shifts later ones.
+        playlist.remove_preset(2).unwrap();
+        assert_eq!(playlist.history(), &[0, 4, 9]);
+
+        playlist.clear_history();
+        assert!(playlist.history().is_empty());
+        assert_eq!(playlist.back(), None);
+    }
+
+    fn random_sequence(playlist: &mut Playlist) -> Vec<u32> {
+        (0..10)
+            .map(|_| {
+                playlist.play_random().unwrap();
+                playlist.play_next();
+                playlist.get_position()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn playlist_remote() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+        playlist.set_shuffle(false);
+
+        let remote = playlist.remote();
+        std::thread::spawn(move || {
+            assert!(remote.set_position(4, true));
+            assert!(remote.play_next());
+            assert!(remote.send(PlaylistCommand::Back));
+        })
+        .join()
+        .unwrap();
+
+        // Nothing happens until the owning thread processes the queue.
+        assert_eq!(playlist.get_position(), 0);
+        assert_eq!(playlist.process_commands().unwrap(), 3);
+        assert_eq!(playlist.get_position(), 4);
+        assert_eq!(playlist.process_commands().unwrap(), 0);
+
+        // A failing command leaves the rest queued.
+        let remote = playlist.remote();
+        remote.set_position(100, true);
+        remote.play_next();
+        assert!(matches!(
+            playlist.process_commands(),
+            Err(Error::IndexOutOfRange { index: 100, .. })
+        ));
+        assert_eq!(playlist.process_commands().unwrap(), 1);
+        assert_eq!(playlist.get_position(), 5);
+
+        drop(playlist);
+        assert!(!remote.play_next());
+    }
+
+    #[test]
+    fn playlist_seeded_rng() {
+        let projectm = ProjectM::create().unwrap();
+
+        let mut first = filled_playlist(&projectm);
+        first.set_shuffle(true);
+        first.set_seed(42);
+        assert!(first.is_deterministic());
+
+        let mut second = filled_playlist(&projectm);
+        second.set_shuffle(true);
+        second.set_seed(42);
+
+        assert_eq!(random_sequence(&mut first), random_sequence(&mut second));
+    }
+
+    #[test]
+    fn playlist_with_rng() {
+        use rand::SeedableRng;
+
+        let projectm = ProjectM::create().unwrap();
+        let rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut first = Playlist::with_rng(&projectm, rng.clone()).unwrap();
+        first.add_path(presets_dir(), true).unwrap();
+        let mut second = Playlist::with_rng(&projectm, rng).unwrap();
+        second.add_path(presets_dir(), true).unwrap();
+
+        assert_eq!(random_sequence(&mut first), random_sequence(&mut second));
+    }
+
+    #[test]
+    fn playlist_seeded_shuffle_plays_every_preset() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+        playlist.set_shuffle(true);
+        playlist.set_seed(3);
+
+        let mut played: Vec<u32> = (0..40)
+            .map(|_| {
+                playlist.play_next();
+                playlist.get_position()
+            })
+            .collect();
+        assert!(played.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let second_cycle = played.split_off(20);
+        for mut cycle in [played, second_cycle] {
+            cycle.sort_unstable();
+            assert_eq!(cycle, (0..20).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn playlist_seeded_rng_empty() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = Playlist::create(&projectm).unwrap();
+        playlist.set_shuffle(true);
+        playlist.set_seed(42);
+
+        playlist.play_next();
+        playlist.play_prev();
+        assert_eq!(playlist.get_position(), 0);
+    }
+
+    #[test]
+    fn playlist_filter_builder() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        let filter = PlaylistFilter::new()
+            .include("**/200-wave.milk")
+            .exclude("**/*-wave-smooth-*.milk")
+            .exclude("**/*-wave*.milk");
+        let report = filter.apply(&mut playlist).unwrap();
+        assert_eq!(report.removed(), &[0, 3, 7]);
+        assert_eq!(report.total(), 10);
+        assert_eq!(playlist.len(), 10);
+        assert_eq!(
+            playlist.get_filter().unwrap(),
+            vec![
+                "+**/200-wave.milk",
+                "-**/*-wave-smooth-*.milk",
+                "-**/*-wave*.milk"
+            ]
+        );
+
+        // The native filter list keeps excluded presets out of later scans.
+        playlist.add_path(presets_dir(), true).unwrap();
+        assert_eq!(playlist.len(), 10);
+        let report = filter.apply(&mut playlist).unwrap();
+        assert_eq!(report.total(), 0);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn playlist_filter_regex() {
+        let projectm = ProjectM::create().unwrap();
+        let mut playlist = filled_playlist(&projectm);
+
+        let filter = PlaylistFilter::new()
+            .include_regex(r"per_frame_init\.milk$")
+            .unwrap()
+            .exclude_regex(r"/1\d\d-[^/]*\.milk$")
+            .unwrap();
+        let report = filter.apply(&mut playlist).unwrap();
+        assert_eq!(report.removed(), &[0, 6]);
+        assert_eq!(playlist.len(), 14);
+
+        playlist.add_path(presets_dir(), true).unwrap();
+        let report = filter.apply(&mut playlist).unwrap();
+        assert_eq!(report.removed_by(1), 6);
+
+        assert!(PlaylistFilter::new().exclude_regex("(").is_err());
+    }
 }
@@ -0,0 +1,603 @@
+#[cfg(test)]
+mod preset {
+    use projectm::preset::eval::{Evaluator, FrameInput};
+    use projectm::preset::expr::{BinaryOp, ExprKind, Program};
+    use projectm::preset::lint::{lint, lint_source, Location, Severity};
+    use projectm::preset::{self, Equation, Header, LineKind, Parameter, Preset};
+    use projectm::Error;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::path::PathBuf;
+
+    fn preset_files() -> Vec<PathBuf> {
+        let presets_dir = std::env::current_dir().unwrap().join("presets");
+        let mut files: Vec<_> = std::fs::read_dir(presets_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "milk"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn load(name: &str) -> Preset {
+        let path = std::env::current_dir().unwrap().join("presets").join(name);
+        Preset::from_file(path).unwrap()
+    }
+
+    #[test]
+    fn test_parse_all_presets() {
+        for path in preset_files() {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let preset = Preset::parse(&source).unwrap();
+
+            assert_eq!(preset.header.as_ref().unwrap().name, "preset00");
+            assert_eq!(preset.lines().len(), source.lines().count(), "{path:?}");
+            for (line, text) in preset.lines().iter().zip(source.lines()) {
+                assert_eq!(&source[line.span.range()], text, "{path:?}");
+            }
+            for parameter in &preset.parameters {
+                assert_eq!(&source[parameter.key_span.range()], parameter.key);
+                assert_eq!(&source[parameter.value_span.range()], parameter.value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_parameters() {
+        let preset = load("001-line.milk");
+        assert_eq!(preset.get_f64("fDecay"), Some(0.0));
+        assert_eq!(preset.get("FDECAY").unwrap().key, "fdecay");
+        assert_eq!(preset.get_f64("fWaveSmoothing"), Some(0.01));
+        assert_eq!(preset.get_f64("nWaveMode"), Some(6.0));
+        assert_eq!(preset.comments.len(), 2);
+        assert!(preset.get("zoom").is_none());
+
+        let preset = Preset::parse("[preset00]\nwarp=0;\r\nzoom=1.5e1x").unwrap();
+        assert_eq!(preset.get("warp").unwrap().value, "0;");
+        assert_eq!(preset.get_f64("warp"), Some(0.0));
+        assert_eq!(preset.get_f64("zoom"), Some(15.0));
+        assert_eq!(preset.lines()[1].span.len(), "warp=0;".len());
+    }
+
+    #[test]
+    fn test_parse_equations() {
+        let preset = load("101-per_frame.milk");
+        assert_eq!(preset.per_frame.len(), 3);
+        assert_eq!(preset.per_frame[0].index, 1000);
+        assert_eq!(preset.per_frame[2].code, "ib_r=0.7+0.4*sin(3*time);");
+        assert!(preset.per_frame_code().starts_with("ib_r="));
+
+        let preset = load("104-continued-eqn.milk");
+        let continued: Vec<_> = preset.per_frame.iter().filter(|e| e.index < 1000).collect();
+        assert_eq!(continued.len(), 2);
+        assert!(preset
+            .per_frame_code()
+            .starts_with("ib_r=0.7+0.4*\n   sin(3*time);"));
+
+        let preset = load("105-per_frame_init.milk");
+        assert_eq!(preset.per_frame_init_code(), "SPEED=10;");
+
+        let preset = load("110-per_pixel.milk");
+        assert_eq!(preset.per_pixel_code(), "zoom=0.9615-rad*0.1;");
+
+        let equations = vec![Equation::new(2, "b=2;"), Equation::new(1, "a=1;")];
+        assert_eq!(Equation::join(&equations), "a=1;\nb=2;");
+
+        // MilkDrop keeps the first of duplicate lines and stops at a gap.
+        let preset = Preset::parse(
+            "[preset00]\nper_frame_2=b=2;\nper_frame_1=a=1;\nper_frame_1=a=2;\n\
+             per_frame_4=d=4;\nwarp_1=`a\nwarp_3=`c\n",
+        )
+        .unwrap();
+        assert_eq!(preset.per_frame_code(), "a=1;\nb=2;");
+        assert_eq!(preset.warp_shader_code(), "a");
+    }
+
+    #[test]
+    fn test_parse_waves_and_shapes() {
+        let preset = load("210-wave-smooth-00.milk");
+        assert_eq!(preset.waves.len(), 1);
+        let wave = preset.wave(0).unwrap();
+        assert_eq!(wave.get("enabled").unwrap().as_f64(), Some(1.0));
+        assert_eq!(wave.get("Smoothing").unwrap().key, "smoothing");
+        assert_eq!(wave.per_point.len(), 2);
+        assert_eq!(wave.per_point[1].code, "y=y+value1;");
+        assert_eq!(preset.get_f64("wave_r"), Some(1.0));
+
+        let source = "[preset00]\n\
+            shapecode_3_sides=4\n\
+            shape_3_init1=t1=0;\n\
+            shape_3_per_frame1=x=0.5;\n\
+            wave_1_init2=b=1;\n\
+            wave_1_per_frame1=a=1;\n\
+            wavecode_0_enabled=0\n\
+            warp_1=`shader_body {\n\
+            comp_1=`shader_body {\n\
+            comp_2=`}\n";
+        let preset = Preset::parse(source).unwrap();
+        let shape = preset.shape(3).unwrap();
+        assert_eq!(shape.get("sides").unwrap().value, "4");
+        assert_eq!(shape.init.len(), 1);
+        assert_eq!(shape.per_frame[0].code, "x=0.5;");
+        assert_eq!(
+            preset.waves.iter().map(|w| w.index).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_eq!(preset.wave(1).unwrap().init[0].index, 2);
+        assert_eq!(preset.warp_shader_code(), "shader_body {");
+        assert_eq!(preset.comp_shader_code(), "shader_body {\n}");
+        assert_eq!(
+            preset.lines()[1].kind,
+            LineKind::ShapeParameter { shape: 3 }
+        );
+        assert!(preset.parameters.is_empty());
+    }
+
+    #[test]
+    fn test_decode() {
+        // A UTF-8 byte order mark, then a Windows-1252 `é` in a comment.
+        let bytes = b"\xEF\xBB\xBF[preset00]\n// by Ren\xE9\nfDecay=0.9\n";
+        let source = preset::decode(bytes);
+        assert!(source.starts_with("[preset00]"));
+
+        let preset = Preset::parse(&source).unwrap();
+        assert_eq!(preset.header.as_ref().unwrap().name, "preset00");
+        assert_eq!(preset.comments[0].text, "// by René");
+        assert_eq!(preset.get_f64("fDecay"), Some(0.9));
+
+        assert_eq!(preset::decode("zoom=1 // ü".as_bytes()), "zoom=1 // ü");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match Preset::parse("[preset00]\nfDecay=1\nnot a setting\n") {
+            Err(Error::PresetSyntax { line, .. }) => assert_eq!(line, 3),
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(matches!(
+            Preset::parse("=1"),
+            Err(Error::PresetSyntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            Preset::parse("[preset00]\n[preset01]"),
+            Err(Error::PresetSyntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            Preset::from_file("presets/does-not-exist.milk"),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_milk_round_trip() {
+        for path in preset_files() {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let preset = Preset::parse(&source).unwrap();
+            assert_eq!(preset.to_milk(), source, "{path:?}");
+        }
+
+        let source = "[preset00]\r\n  // note\r\nfDecay = 0.5\r\n\r\nper_frame_1=a=1;";
+        assert_eq!(Preset::parse(source).unwrap().to_milk(), source);
+    }
+
+    #[test]
+    fn test_to_milk_edited() {
+        let source = "[preset00]\n\
+            // comment\n\
+            fDecay=0.98\n\
+            zoom=1.0\n\
+            \n\
+            per_frame_1=a=1;\n\
+            per_frame_2=b=2;\n\
+            wavecode_0_enabled=1\n\
+            wave_0_per_point1=x=sample;";
+        let mut preset = Preset::parse(source).unwrap();
+        preset.parameters[0].value = "0.5".to_string();
+        preset.parameters.remove(1);
+        preset.per_frame[1].index = 3;
+        preset.parameters.push(Parameter::new("rot", "0.1"));
+        preset.waves[0]
+            .per_point
+            .push(Equation::new(2, "y=value1;"));
+
+        assert_eq!(
+            preset.to_milk(),
+            "[preset00]\n\
+            // comment\n\
+            fDecay=0.5\n\
+            \n\
+            per_frame_1=a=1;\n\
+            per_frame_3=b=2;\n\
+            wavecode_0_enabled=1\n\
+            wave_0_per_point1=x=sample;\n\
+            rot=0.1\n\
+            wave_0_per_point2=y=value1;\n"
+        );
+
+        let reparsed = Preset::parse(&preset.to_milk()).unwrap();
+        assert_eq!(reparsed.get_f64("fDecay"), Some(0.5));
+        assert_eq!(reparsed.per_frame.len(), 2);
+        // `per_frame_2` is missing now, so MilkDrop stops after `a=1;`.
+        assert_eq!(reparsed.per_frame_code(), "a=1;");
+        assert_eq!(reparsed.wave(0).unwrap().per_point.len(), 2);
+
+        let mut preset = Preset::new();
+        preset.header = Some(Header {
+            name: "preset00".to_string(),
+            line: 0,
+            span: Default::default(),
+        });
+        preset.parameters.push(Parameter::new("fDecay", "1"));
+        preset.per_frame.push(Equation::new(1, "zoom=1;"));
+        assert_eq!(
+            preset.to_milk(),
+            "[preset00]\nfDecay=1\nper_frame_1=zoom=1;\n"
+        );
+    }
+
+    #[test]
+    fn test_expr_parse_presets() {
+        for path in preset_files() {
+            let preset = Preset::from_file(&path).unwrap();
+            let mut blocks = vec![&preset.per_frame_init, &preset.per_frame, &preset.per_pixel];
+            for wave in &preset.waves {
+                blocks.extend([&wave.init, &wave.per_frame, &wave.per_point]);
+            }
+
+            for equations in blocks {
+                let program = Program::parse_equations(equations).unwrap();
+                let printed = program.to_string();
+                let reparsed = Program::parse(&printed).unwrap();
+                assert_eq!(reparsed.to_string(), printed, "{path:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_expr_ast() {
+        let code = "ib_r=0.7+0.4*sin(3*time);";
+        let program = Program::parse(code).unwrap();
+        assert_eq!(program.statements.len(), 1);
+        let ExprKind::Assign { target, value, .. } = &program.statements[0].kind else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(target.kind, ExprKind::Variable("ib_r".to_string()));
+        let ExprKind::Binary { op, right, .. } = &value.kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(*op, BinaryOp::Add);
+        let ExprKind::Binary { right: call, .. } = &right.kind else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(&code[call.span.range()], "sin(3*time)");
+        assert_eq!(program.to_string(), "ib_r = 0.7 + 0.4 * sin(3 * time);");
+
+        let preset = load("103-multiple-eqn.milk");
+        let program = Program::parse_equations(&preset.per_frame).unwrap();
+        assert_eq!(program.statements.len(), 3);
+        let source = preset.source();
+        let spans: Vec<_> = program
+            .statements
+            .iter()
+            .map(|statement| &source[statement.span.range()])
+            .collect();
+        assert_eq!(spans[1], "ib_g=0.7+0.4*sin(4*time)");
+
+        let preset = load("104-continued-eqn.milk");
+        let program = Program::parse_equations(&preset.per_frame).unwrap();
+        assert_eq!(program.statements.len(), 1);
+        let text = &preset.source()[program.statements[0].span.range()];
+        assert!(text.starts_with("ib_r=0.7+0.4*\n"));
+        assert!(text.ends_with("sin(3*time)"));
+        assert_eq!(program.to_string(), "ib_r = 0.7 + 0.4 * sin(3 * time);");
+
+        assert!(Program::parse("// just a comment\n/* and another */")
+            .unwrap()
+            .statements
+            .is_empty());
+    }
+
+    #[test]
+    fn test_expr_print() {
+        let print = |code: &str| Program::parse(code).unwrap().to_string();
+
+        assert_eq!(print("a=-2^2+(b?c:d)*3"), "a = -2 ^ 2 + (b ? c : d) * 3;");
+        assert_eq!(print("x=(1-2)-(3-4)"), "x = 1 - 2 - (3 - 4);");
+        assert_eq!(
+            print("x=2^3^4;y=(2^3)^4"),
+            "x = 2 ^ 3 ^ 4;\ny = (2 ^ 3) ^ 4;"
+        );
+        assert_eq!(print("a=b=c;a+=(b=1)"), "a = b = c;\na += b = 1;");
+        assert_eq!(
+            print("q1=if(above(bass,1)&&!below(mid,.5),sqr(treb),rand(10))"),
+            "q1 = if(above(bass, 1) && !below(mid, 0.5), sqr(treb), rand(10));"
+        );
+        assert_eq!(
+            print("megabuf(3)=equal(x,$PI)"),
+            format!("megabuf(3) = equal(x, {});", std::f64::consts::PI)
+        );
+        assert_eq!(print("a = 1e3 % 7 | 2 & 1 ;;"), "a = 1000 % 7 | 2 & 1;");
+        assert_eq!(
+            print("_set(a, _add(b, _mul(c, 2))); _addop(megabuf(1), _not(x))"),
+            "a = b + c * 2;\nmegabuf(1) += !x;"
+        );
+        assert_eq!(print("_orop(a, 4)"), "a = a | 4;");
+        assert_eq!(
+            print("m=memset(0, 1, freembuf(8))"),
+            "m = memset(0, 1, freembuf(8));"
+        );
+    }
+
+    #[test]
+    fn test_expr_errors() {
+        let error = |code: &str| match Program::parse(code) {
+            Err(Error::ExpressionSyntax { span, message }) => (span.start, message),
+            other => panic!("unexpected result {other:?}"),
+        };
+
+        assert_eq!(error("a = sin(1, 2)").0, 4);
+        assert!(error("a = sin(1, 2)").1.contains("takes 1 argument"));
+        assert_eq!(
+            error("a = foo(1)"),
+            (4, "unknown function `foo`".to_string())
+        );
+        assert!(error("a = (1").1.contains("expected `)`"));
+        assert_eq!(error("1 = 2").0, 0);
+        assert_eq!(error("a = 1 b = 2").0, 6);
+        assert!(error("/* open").1.contains("unterminated"));
+        assert_eq!(error("a = 1 # 2").0, 6);
+
+        // Deep nesting is an error instead of a stack overflow.
+        let deep =
+            |open: &str, close: &str| format!("a={}1{}", open.repeat(50_000), close.repeat(50_000));
+        for code in [
+            deep("(", ")"),
+            deep("-", ""),
+            deep("sin(", ")"),
+            deep("1^", ""),
+        ] {
+            assert_eq!(error(&code).1, "expression nested too deeply");
+        }
+        let nested = format!("a={}1{};", "(".repeat(60), ")".repeat(60));
+        assert_eq!(Program::parse(&nested).unwrap().to_string(), "a = 1;");
+
+        let equations = [Equation::new(1, "a = 1;"), Equation::new(2, "b = )")];
+        let Err(Error::ExpressionSyntax { span, .. }) = Program::parse_equations(&equations) else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!(span.start, 4);
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_eval_per_frame() {
+        let preset = load("101-per_frame.milk");
+        let mut evaluator = Evaluator::new(&preset).unwrap();
+        for frame in 0..30 {
+            let input = FrameInput::at_frame(frame, 30.0);
+            evaluator.run_frame(&input);
+            assert_close(evaluator.get("ib_r"), 0.7 + 0.4 * (3.0 * input.time).sin());
+            assert_close(evaluator.get("decay"), 0.98);
+        }
+        assert_eq!(evaluator.frames(), 30);
+
+        let preset = load("105-per_frame_init.milk");
+        let mut evaluator = Evaluator::new(&preset).unwrap();
+        evaluator.run_frames(10, 60.0);
+        assert_eq!(evaluator.get("speed"), 10.0);
+        assert_eq!(evaluator.get("time"), 9.0 / 60.0);
+        assert_close(
+            evaluator.get("IB_R"),
+            0.7 + 0.4 * (9.0 / 60.0 * 10.0f64).sin(),
+        );
+
+        let preset = load("104-continued-eqn.milk");
+        let mut evaluator = Evaluator::new(&preset).unwrap();
+        evaluator.run_frame(&FrameInput::at_frame(45, 60.0));
+        assert_close(evaluator.get("ib_r"), 0.7 + 0.4 * (3.0 * 0.75f64).sin());
+
+        // Only the first `per_frame_1` runs, and `per_frame_3` follows a gap.
+        let preset =
+            Preset::parse("[preset00]\nper_frame_1=a=1;\nper_frame_1=b=2;\nper_frame_3=c=3;")
+                .unwrap();
+        let mut evaluator = Evaluator::new(&preset).unwrap();
+        evaluator.run_frame(&FrameInput::at_frame(0, 60.0));
+        assert_eq!(evaluator.get("a"), 1.0);
+        assert_eq!(evaluator.get("b"), 0.0);
+        assert_eq!(evaluator.get("c"), 0.0);
+    }
+
+    #[test]
+    fn test_eval_frame_state() {
+        let source = "[preset00]\n\
+            zoom=0.5\n\
+            per_frame_init_1=q1=5; counter=100;\n\
+            per_frame_1=q1=q1+1; zoom=zoom*2; counter=counter+1;\n\
+            per_frame_2=megabuf(2)+=1; total=megabuf(2.5);\n\
+            per_frame_3=d=1/0; m=7%0; r=rand(10); bits=6|1&3;\n\
+            per_frame_4=n=0; loop(5, n+=1); while(exec2(n-=1, n>0));\n\
+            per_frame_5=t=if(equal(frame,1),above(bass,0.5),below(1,2)) ? 3 : 4;";
+        let preset = Preset::parse(source).unwrap();
+        let mut evaluator = Evaluator::with_rng(&preset, ChaCha8Rng::seed_from_u64(1)).unwrap();
+
+        evaluator.run_frames(3, 60.0);
+        assert_eq!(evaluator.q(1), 6.0);
+        assert_eq!(evaluator.get("zoom"), 1.0);
+        assert_eq!(evaluator.get("counter"), 103.0);
+        assert_eq!(evaluator.get("total"), 3.0);
+        assert_eq!(evaluator.get("d"), 0.0);
+        assert_eq!(evaluator.get("m"), 0.0);
+        assert_eq!(evaluator.get("bits"), 7.0);
+        assert_eq!(evaluator.get("n"), 0.0);
+        assert_eq!(evaluator.get("t"), 3.0);
+        assert_eq!(evaluator.get("undefined"), 0.0);
+        let r = evaluator.get("r");
+        assert!((0.0..10.0).contains(&r) && r.fract() == 0.0);
+
+        let mut again = Evaluator::with_rng(&preset, ChaCha8Rng::seed_from_u64(1)).unwrap();
+        again.run_frames(3, 60.0);
+        assert_eq!(again.get("r"), r);
+        assert!(again.variables().windows(2).all(|w| w[0].0 < w[1].0));
+
+        // ns-eel's memory functions and function forms of the operators.
+        let source = "[preset00]\n\
+            per_frame_1=memset(10, 2, 3); memcpy(11, 10, 3); _set(a, megabuf(13));\n\
+            per_frame_2=b=_mem(12); freembuf(12); c=megabuf(12) + megabuf(11);\n\
+            per_frame_3=assign(d, 6); _powop(d, 2); _addop(d, _neg(_if(1, 2, 3)));";
+        let preset = Preset::parse(source).unwrap();
+        let mut evaluator = Evaluator::new(&preset).unwrap();
+        evaluator.run_frame(&FrameInput::at_frame(0, 60.0));
+        assert_eq!(evaluator.get("a"), 2.0);
+        assert_eq!(evaluator.get("b"), 2.0);
+        assert_eq!(evaluator.get("c"), 2.0);
+        assert_eq!(evaluator.get("d"), 34.0);
+    }
+
+    fn codes(source: &str) -> Vec<(&'static str, usize)> {
+        lint_source(source)
+            .iter()
+            .map(|d| (d.code, d.location(source).0.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_presets() {
+        for path in preset_files() {
+            let preset = Preset::from_file(&path).unwrap();
+            let diagnostics = lint(&preset);
+            if path.ends_with("000-empty.milk") {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].code, "empty-preset");
+            } else {
+                assert!(diagnostics.is_empty(), "{path:?}: {diagnostics:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_lint_checks() {
+        let source = "[preset00]\n\
+            fDecay=-0.5\n\
+            fdecay=0.9\n\
+            nWaveMode=2.5\n\
+            fZoom=1\n\
+            wave_r=abc\n\
+            per_frame_init_1=q1=1; q2=2; q3=3;\n\
+            per_frame_1=zoom=zoom+speed*0.1;\n\
+            per_frame_1=rot=1;\n\
+            per_frame_3=x=q2;\n\
+            per_frame_4=y=speed;\n\
+            per_pixel_1=rot=rot+q3*rad;\n\
+            wavecode_0_bogus=1\n\
+            wave_0_per_point1=x=sample+t9;\n";
+        assert_eq!(
+            codes(source),
+            [
+                ("out-of-range", 2),
+                ("duplicate-key", 3),
+                ("out-of-range", 4),
+                ("unknown-key", 5),
+                ("invalid-value", 6),
+                ("unused-q", 7),
+                // `x=q2` follows the gap, so MilkDrop never reads `q2`.
+                ("unused-q", 7),
+                ("undefined-variable", 8),
+                ("duplicate-index", 9),
+                ("numbering-gap", 10),
+                ("unknown-key", 13),
+                ("undefined-variable", 14),
+            ]
+        );
+
+        assert_eq!(
+            codes("[preset00]\nper_frame_1=a=sin(;\nper_frame_2=b=c;"),
+            [("syntax-error", 2)]
+        );
+        assert_eq!(codes("[preset00]\nnot a setting"), [("syntax-error", 2)]);
+        assert_eq!(codes("[preset00]\nwave_r=1.0\n"), [("empty-preset", 1)]);
+        assert!(codes("[preset00]\nwave_r=0.5\n").is_empty());
+        assert!(codes("[preset00]\nper_frame_1=q1=1;\nwarp_1=`ret = q1;\n").is_empty());
+
+        let diagnostics = lint_source("[preset00]\nper_frame_1=a=b(1);");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].message, "unknown function `b`");
+    }
+
+    #[test]
+    fn test_lint_render() {
+        let source = "[preset00]\nfDecay=-0.5\n";
+        let diagnostic = &lint_source(source)[0];
+        assert_eq!(
+            diagnostic.location(source),
+            (
+                Location { line: 2, column: 8 },
+                Location {
+                    line: 2,
+                    column: 12
+                }
+            )
+        );
+        assert_eq!(
+            diagnostic.render("test.milk", source),
+            "warning[out-of-range]: `fDecay` should be a value from 0 to 1, found -0.5\n \
+             --> test.milk:2:8\n  \
+             |\n\
+             2 | fDecay=-0.5\n  \
+             |        ^^^^\n"
+        );
+    }
+
+    #[cfg(feature = "lint")]
+    #[test]
+    fn test_lint_cli() {
+        /// Removed again when dropped, even if the test fails.
+        struct TempFile(PathBuf);
+
+        impl Drop for TempFile {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+
+        let bad = TempFile(
+            std::env::temp_dir().join(format!("projectm-lint-test-{}.milk", std::process::id())),
+        );
+        let bad = &bad.0;
+        std::fs::write(bad, "[preset00]\nfDecay=2\nper_frame_1=a=(;\n").unwrap();
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_projectm-lint"))
+            .args(["--format", "json"])
+            .arg(bad)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let diagnostics = json.as_array().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0]["code"], "out-of-range");
+        assert_eq!(diagnostics[0]["line"], 2);
+        assert_eq!(diagnostics[1]["severity"], "error");
+        assert_eq!(diagnostics[1]["column"], 16);
+
+        let presets = std::env::current_dir().unwrap().join("presets");
+        let status = |args: &[&str]| {
+            std::process::Command::new(env!("CARGO_BIN_EXE_projectm-lint"))
+                .args(args)
+                .arg(&presets)
+                .output()
+                .unwrap()
+                .status
+                .code()
+        };
+        assert_eq!(status(&[]), Some(0));
+        assert_eq!(status(&["--deny-warnings"]), Some(1));
+        assert_eq!(status(&["--format", "xml"]), Some(2));
+    }
+}
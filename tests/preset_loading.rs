@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod preset_loading {
+    use projectm::core::ProjectM;
+    use std::ffi::CStr;
+    use std::path::PathBuf;
+
+    fn preset_files() -> Vec<PathBuf> {
+        let presets_dir = std::env::current_dir().unwrap().join("presets");
+        let mut files: Vec<_> = std::fs::read_dir(presets_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "milk"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// A buffer whose last byte sits directly in front of an inaccessible
+    /// guard page, so any read past the end of the data faults immediately.
+    #[cfg(unix)]
+    struct GuardedBuffer {
+        base: *mut libc::c_void,
+        mapped: usize,
+        offset: usize,
+        len: usize,
+    }
+
+    #[cfg(unix)]
+    impl GuardedBuffer {
+        fn new(data: &[u8]) -> Self {
+            let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+            let data_pages = (data.len() + page - 1) / page;
+            let mapped = (data_pages.max(1) + 1) * page;
+
+            let base = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    mapped,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            assert_ne!(base, libc::MAP_FAILED);
+
+            let guard_offset = mapped - page;
+            let result = unsafe {
+                libc::mprotect(
+                    base.cast::<u8>().add(guard_offset).cast(),
+                    page,
+                    libc::PROT_NONE,
+                )
+            };
+            assert_eq!(result, 0);
+
+            let offset = guard_offset - data.len();
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    data.as_ptr(),
+                    base.cast::<u8>().add(offset),
+                    data.len(),
+                );
+            }
+
+            GuardedBuffer {
+                base,
+                mapped,
+                offset,
+                len: data.len(),
+            }
+        }
+
+        fn as_bytes(&self) -> &[u8] {
+            unsafe { std::slice::from_raw_parts(self.base.cast::<u8>().add(self.offset), self.len) }
+        }
+    }
+
+    #[cfg(unix)]
+    impl Drop for GuardedBuffer {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.base, self.mapped) };
+        }
+    }
+
+    #[test]
+    fn load_every_preset_by_path() {
+        let projectm = ProjectM::create().unwrap();
+        for file in preset_files() {
+            projectm.load_preset_file(&file, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn load_every_preset_by_data() {
+        let projectm = ProjectM::create().unwrap();
+        for file in preset_files() {
+            let data = std::fs::read_to_string(&file).unwrap();
+            projectm.load_preset_data(&data, false).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_preset_data_does_not_read_past_str() {
+        // The string ends right at the guard page and has no NUL terminator,
+        // so passing it to projectM without copying would fault.
+        let projectm = ProjectM::create().unwrap();
+        for file in preset_files() {
+            let data = std::fs::read(&file).unwrap();
+            let buffer = GuardedBuffer::new(&data);
+            let data = std::str::from_utf8(buffer.as_bytes()).unwrap();
+            projectm.load_preset_data(data, false).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_preset_data_cstr_stops_at_nul() {
+        let projectm = ProjectM::create().unwrap();
+        for file in preset_files() {
+            let mut data = std::fs::read(&file).unwrap();
+            data.push(0);
+            let buffer = GuardedBuffer::new(&data);
+            let data = CStr::from_bytes_with_nul(buffer.as_bytes()).unwrap();
+            projectm.load_preset_data_cstr(data, false);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_preset_file_cstr_stops_at_nul() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let projectm = ProjectM::create().unwrap();
+        for file in preset_files() {
+            let mut path = file.as_os_str().as_bytes().to_vec();
+            path.push(0);
+            let buffer = GuardedBuffer::new(&path);
+            let path = CStr::from_bytes_with_nul(buffer.as_bytes()).unwrap();
+            projectm.load_preset_file_cstr(path, false);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_preset_file_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let projectm = ProjectM::create().unwrap();
+        let path = PathBuf::from(OsStr::from_bytes(b"presets/\xff-missing.milk"));
+        projectm.load_preset_file(path, false).unwrap();
+    }
+
+    #[test]
+    fn load_preset_rejects_interior_nul() {
+        let projectm = ProjectM::create().unwrap();
+        assert!(projectm.load_preset_file("presets/\0.milk", false).is_err());
+        assert!(projectm.load_preset_data("[preset00]\0", false).is_err());
+    }
+}